@@ -0,0 +1,108 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Code to generate the methods of `Client` from TL definitions.
+
+use crate::functions::write_request;
+use crate::metadata::Metadata;
+use crate::rustifier;
+use std::io::{self, Write};
+use tdlib_tl_parser::tl::{Category, Definition};
+
+/// Defines the method corresponding to the definition:
+///
+/// ```ignore
+/// pub async fn name(&self, field: Type) -> Result {
+///
+/// }
+/// ```
+fn write_method<W: Write>(
+    file: &mut W,
+    def: &Definition,
+    _metadata: &Metadata,
+    gen_bots_only_api: bool,
+) -> io::Result<()> {
+    if rustifier::definitions::is_for_bots_only(def) && !gen_bots_only_api {
+        return Ok(());
+    }
+
+    let params = def
+        .params
+        .iter()
+        .filter(|p| !rustifier::parameters::is_for_bots_only(p) || gen_bots_only_api)
+        .collect::<Vec<_>>();
+
+    // Documentation
+    writeln!(file, "{}", rustifier::definitions::description(def, "    "))?;
+    if !params.is_empty() {
+        writeln!(file, "    /// # Arguments")?;
+    }
+    for param in params.iter() {
+        writeln!(
+            file,
+            "    /// * `{}` - {}",
+            rustifier::parameters::attr_name(param),
+            param.description.replace('\n', "\n    /// ")
+        )?;
+    }
+
+    // Method
+    writeln!(file, "    #[allow(clippy::too_many_arguments)]")?;
+    write!(
+        file,
+        "    pub async fn {}(&self",
+        rustifier::definitions::function_name(def)
+    )?;
+    for param in params.iter() {
+        write!(file, ", {}: ", rustifier::parameters::attr_name(param))?;
+
+        let is_optional = rustifier::parameters::is_optional(param);
+        if is_optional {
+            write!(file, "Option<")?;
+        }
+        write!(file, "{}", rustifier::parameters::qual_name(param))?;
+        if is_optional {
+            write!(file, ">")?;
+        }
+    }
+    writeln!(
+        file,
+        ") -> Result<{}, crate::Error> {{",
+        rustifier::types::qual_name(&def.ty, false)
+    )?;
+
+    // Send request
+    write!(file, "        self.send(")?;
+    write_request(file, def, "crate::functions::", gen_bots_only_api)?;
+    writeln!(file, ").await")?;
+
+    writeln!(file, "    }}")?;
+    Ok(())
+}
+
+/// Write the `impl` block with the methods of `Client`.
+pub(crate) fn write_client_impl<W: Write>(
+    mut file: &mut W,
+    definitions: &[Definition],
+    metadata: &Metadata,
+    gen_bots_only_api: bool,
+) -> io::Result<()> {
+    // Begin impl block
+    writeln!(file, "impl crate::Client {{")?;
+
+    let functions = definitions
+        .iter()
+        .filter(|d| d.category == Category::Functions);
+
+    for definition in functions {
+        write_method(&mut file, definition, metadata, gen_bots_only_api)?;
+    }
+
+    // End impl block
+    writeln!(file, "}}")
+}
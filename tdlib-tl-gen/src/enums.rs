@@ -22,16 +22,28 @@ use tdlib_tl_parser::tl::{Category, Definition, Type};
 ///     Variant(crate::types::Name),
 /// }
 /// ```
+///
+/// When `gen_unknown_variants` is set, a last `Unrecognized(serde_json::Value)`
+/// variant catches the types not known at generation time. It isn't named
+/// `Unknown` since some types already have a variant with that name.
 fn write_enum<W: Write>(
     file: &mut W,
     ty: &Type,
     metadata: &Metadata,
     gen_bots_only_api: bool,
+    gen_unknown_variants: bool,
 ) -> io::Result<()> {
-    writeln!(
-        file,
-        "    #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]",
-    )?;
+    // With the unknown variant, `Deserialize` is implemented separately, and
+    // the enums with a single type get a second, small variant
+    if gen_unknown_variants {
+        writeln!(file, "    #[allow(clippy::large_enum_variant)]")?;
+        writeln!(file, "    #[derive(Clone, Debug, PartialEq, Serialize)]")?;
+    } else {
+        writeln!(
+            file,
+            "    #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]",
+        )?;
+    }
     writeln!(file, "    #[serde(tag = \"@type\")]")?;
     writeln!(file, "    pub enum {} {{", rustifier::types::type_name(ty))?;
     for d in metadata.defs_with_type(ty) {
@@ -69,6 +81,99 @@ fn write_enum<W: Write>(
 
         writeln!(file, "),")?;
     }
+
+    if gen_unknown_variants {
+        writeln!(
+            file,
+            "        /// A type unknown to this version of the crate, usually added by a newer TdLib version, kept as raw JSON"
+        )?;
+        // Serialized back as the raw JSON, without adding a tag
+        writeln!(file, "        #[serde(untagged)]")?;
+        writeln!(file, "        Unrecognized(serde_json::Value),")?;
+    }
+    writeln!(file, "    }}")?;
+
+    if gen_unknown_variants {
+        write_deserialize_with_unknown(file, ty, metadata, gen_bots_only_api)?;
+    }
+    Ok(())
+}
+
+/// Writes the `Deserialize` implementation of an enumeration with an unknown
+/// variant, such as the following rust code:
+///
+/// ```ignore
+/// impl<'de> Deserialize<'de> for Name {
+///     fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+///         let raw = Box::<serde_json::value::RawValue>::deserialize(deserializer)?;
+///         let tag: TypeTag = serde_json::from_str(raw.get()).map_err(serde::de::Error::custom)?;
+///         match tag.r#type.as_deref() {
+///             Some("name") => serde_json::from_str::<crate::types::Name>(raw.get()).map(Name::Variant).map_err(serde::de::Error::custom),
+///             _ => serde_json::from_str(raw.get()).map(Name::Unrecognized).map_err(serde::de::Error::custom),
+///         }
+///     }
+/// }
+/// ```
+///
+/// Unlike an untagged variant, which also catches the known types failing to
+/// deserialize, only the unknown types end up in the unknown variant. The
+/// object is kept as raw JSON while reading its type, so that the known types
+/// are deserialized once, and only the unknown ones are turned into a
+/// `serde_json::Value`.
+fn write_deserialize_with_unknown<W: Write>(
+    file: &mut W,
+    ty: &Type,
+    metadata: &Metadata,
+    gen_bots_only_api: bool,
+) -> io::Result<()> {
+    let name = rustifier::types::type_name(ty);
+    writeln!(file, "    impl<'de> Deserialize<'de> for {} {{", name)?;
+    writeln!(
+        file,
+        "        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {{"
+    )?;
+    writeln!(
+        file,
+        "            let raw = Box::<serde_json::value::RawValue>::deserialize(deserializer)?;"
+    )?;
+    writeln!(
+        file,
+        "            let tag: TypeTag = serde_json::from_str(raw.get()).map_err(serde::de::Error::custom)?;"
+    )?;
+    writeln!(file, "            match tag.r#type.as_deref() {{")?;
+    for d in metadata.defs_with_type(ty) {
+        if rustifier::definitions::is_for_bots_only(d) && !gen_bots_only_api {
+            continue;
+        }
+
+        let variant = rustifier::definitions::variant_name(d);
+        write!(file, "                Some(\"{}\") => ", d.name)?;
+        if d.params.is_empty() {
+            writeln!(file, "Ok({}::{}),", name, variant)?;
+            continue;
+        }
+
+        write!(
+            file,
+            "serde_json::from_str::<{}>(raw.get())",
+            rustifier::definitions::qual_name(d)
+        )?;
+        if metadata.is_recursive_def(d) {
+            write!(file, ".map(Box::new)")?;
+        }
+        writeln!(
+            file,
+            ".map({}::{}).map_err(serde::de::Error::custom),",
+            name, variant
+        )?;
+    }
+    writeln!(
+        file,
+        "                _ => serde_json::from_str(raw.get()).map({}::Unrecognized).map_err(serde::de::Error::custom),",
+        name
+    )?;
+    writeln!(file, "            }}")?;
+    writeln!(file, "        }}")?;
     writeln!(file, "    }}")?;
     Ok(())
 }
@@ -79,10 +184,23 @@ pub(crate) fn write_enums_mod<W: Write>(
     definitions: &[Definition],
     metadata: &Metadata,
     gen_bots_only_api: bool,
+    gen_unknown_variants: bool,
 ) -> io::Result<()> {
     // Begin outermost mod
     writeln!(file, "pub mod enums {{")?;
     writeln!(file, "    use serde::{{Deserialize, Serialize}};")?;
+    if gen_unknown_variants {
+        writeln!(file)?;
+        writeln!(
+            file,
+            "    /// The `@type` of an object, read without deserializing the rest of it."
+        )?;
+        writeln!(file, "    #[derive(Deserialize)]")?;
+        writeln!(file, "    struct TypeTag<'a> {{")?;
+        writeln!(file, "        #[serde(rename = \"@type\", borrow)]")?;
+        writeln!(file, "        r#type: Option<std::borrow::Cow<'a, str>>,")?;
+        writeln!(file, "    }}")?;
+    }
 
     let mut enums: Vec<&Type> = definitions
         .iter()
@@ -92,7 +210,13 @@ pub(crate) fn write_enums_mod<W: Write>(
     enums.dedup();
 
     for ty in enums {
-        write_enum(&mut file, ty, metadata, gen_bots_only_api)?;
+        write_enum(
+            &mut file,
+            ty,
+            metadata,
+            gen_bots_only_api,
+            gen_unknown_variants,
+        )?;
     }
 
     // End outermost mod
@@ -7,12 +7,271 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! Code to generate Rust's `fn`'s from TL definitions.
+//! Code to generate Rust's `fn`'s and request `struct`'s from TL definitions.
 
 use crate::metadata::Metadata;
 use crate::rustifier;
 use std::io::{self, Write};
-use tdlib_tl_parser::tl::{Category, Definition};
+use tdlib_tl_parser::tl::{Category, Definition, Parameter};
+
+/// The most required parameters a request can have for its builder to have a
+/// constructor, like the functions clippy doesn't warn about.
+const MAX_CONSTRUCTOR_ARGS: usize = 7;
+
+/// Defines the request `struct` corresponding to the definition, together
+/// with its implementation of `Function`:
+///
+/// ```ignore
+/// pub struct Name {
+///     pub field: Type,
+/// }
+///
+/// impl crate::Function for Name {
+///     type Output = Type;
+/// }
+/// ```
+fn write_struct<W: Write>(
+    file: &mut W,
+    def: &Definition,
+    gen_bots_only_api: bool,
+) -> io::Result<()> {
+    if rustifier::definitions::is_for_bots_only(def) && !gen_bots_only_api {
+        return Ok(());
+    }
+
+    writeln!(file, "{}", rustifier::definitions::description(def, "    "))?;
+
+    let serde_as = def
+        .params
+        .iter()
+        .any(|p| rustifier::parameters::serde_as(p).is_some());
+
+    if serde_as {
+        writeln!(file, "    #[serde_as]",)?;
+    }
+
+    let params = def
+        .params
+        .iter()
+        .filter(|p| !rustifier::parameters::is_for_bots_only(p) || gen_bots_only_api)
+        .collect::<Vec<_>>();
+
+    write!(file, "    #[derive(Clone, Debug, ")?;
+    if params
+        .iter()
+        .all(|p| rustifier::parameters::is_optional(p) || rustifier::parameters::is_flag(p))
+    {
+        write!(file, "Default, ")?;
+    }
+    writeln!(file, "PartialEq, Deserialize, Serialize)]")?;
+    writeln!(
+        file,
+        "    #[serde(tag = \"@type\", rename = \"{}\")]",
+        def.name
+    )?;
+    writeln!(
+        file,
+        "    pub struct {} {{",
+        rustifier::definitions::type_name(def),
+    )?;
+
+    for param in params.iter() {
+        writeln!(
+            file,
+            "{}",
+            rustifier::parameters::description(param, "        ")
+        )?;
+
+        let attr_name = rustifier::parameters::attr_name(param);
+        if attr_name.trim_start_matches("r#") != param.name {
+            writeln!(file, "        #[serde(rename = \"{}\")]", param.name)?;
+        }
+        if let Some(serde_as) = rustifier::parameters::serde_as(param) {
+            writeln!(file, "        #[serde_as(as = \"{}\")]", serde_as)?;
+        }
+        write!(file, "        pub {}: ", attr_name)?;
+
+        let is_optional = rustifier::parameters::is_optional(param);
+        if is_optional {
+            write!(file, "Option<")?;
+        }
+        write!(file, "{}", rustifier::parameters::qual_name(param))?;
+        if is_optional {
+            write!(file, ">")?;
+        }
+
+        writeln!(file, ",")?;
+    }
+
+    writeln!(file, "    }}")?;
+
+    write_builder(file, def, &params)?;
+
+    // Function implementation
+    writeln!(
+        file,
+        "    impl crate::Function for {} {{",
+        rustifier::definitions::type_name(def),
+    )?;
+    writeln!(
+        file,
+        "        type Output = {};",
+        rustifier::types::qual_name(&def.ty, false)
+    )?;
+    writeln!(file, "        const TYPE: &'static str = \"{}\";", def.name)?;
+    if rustifier::definitions::is_idempotent(def) {
+        writeln!(file, "        const IDEMPOTENT: bool = true;")?;
+    }
+    writeln!(
+        file,
+        "        fn parse_response(response: &str) -> Result<Self::Output, crate::Error> {{"
+    )?;
+    if rustifier::types::is_ok(&def.ty) {
+        writeln!(file, "            crate::parse_ok_response(response)")?;
+    } else {
+        writeln!(file, "            crate::parse_response(response)")?;
+    }
+    writeln!(file, "        }}")?;
+    writeln!(file, "    }}")?;
+    Ok(())
+}
+
+/// Defines the builder methods of the request `struct` corresponding to the
+/// definition, taking the required parameters in the constructor, and the
+/// optional ones and the flags with setters:
+///
+/// ```ignore
+/// impl Name {
+///     pub fn new(field: Type) -> Self {
+///
+///     }
+///
+///     pub fn optional_field(mut self, optional_field: Type) -> Self {
+///
+///     }
+///
+///     pub fn flag(mut self, flag: bool) -> Self {
+///
+///     }
+/// }
+/// ```
+///
+/// The flags are left out of the constructor so that the calls don't read as
+/// rows of `true` and `false`, and are `false` until set. The requests with
+/// more required parameters than `MAX_CONSTRUCTOR_ARGS` have no constructor,
+/// and are built with a `struct` literal instead.
+fn write_builder<W: Write>(
+    file: &mut W,
+    def: &Definition,
+    params: &[&Parameter],
+) -> io::Result<()> {
+    let (set, required): (Vec<&Parameter>, Vec<&Parameter>) = params
+        .iter()
+        .copied()
+        .partition(|p| rustifier::parameters::is_optional(p) || rustifier::parameters::is_flag(p));
+
+    if required.len() > MAX_CONSTRUCTOR_ARGS {
+        writeln!(
+            file,
+            "    /// The request has too many required parameters for a constructor, so it's built with a `struct` literal."
+        )?;
+    }
+    writeln!(
+        file,
+        "    impl {} {{",
+        rustifier::definitions::type_name(def)
+    )?;
+
+    // Constructor
+    if required.len() <= MAX_CONSTRUCTOR_ARGS {
+        write_constructor(file, def, params, &required)?;
+    }
+
+    // Setters
+    for param in set.iter() {
+        let attr_name = rustifier::parameters::attr_name(param);
+        writeln!(
+            file,
+            "{}",
+            rustifier::parameters::description(param, "        ")
+        )?;
+        if rustifier::parameters::is_flag(param) {
+            writeln!(
+                file,
+                "        pub fn {0}(mut self, {0}: bool) -> Self {{",
+                attr_name
+            )?;
+            writeln!(file, "            self.{0} = {0};", attr_name)?;
+        } else {
+            writeln!(
+                file,
+                "        pub fn {0}(mut self, {0}: {1}) -> Self {{",
+                attr_name,
+                rustifier::parameters::qual_name(param)
+            )?;
+            writeln!(file, "            self.{0} = Some({0});", attr_name)?;
+        }
+        writeln!(file, "            self")?;
+        writeln!(file, "        }}")?;
+    }
+
+    writeln!(file, "    }}")?;
+    Ok(())
+}
+
+/// Defines the constructor of the request, taking its required parameters.
+fn write_constructor<W: Write>(
+    file: &mut W,
+    def: &Definition,
+    params: &[&Parameter],
+    required: &[&Parameter],
+) -> io::Result<()> {
+    writeln!(
+        file,
+        "        /// Create the request with its required parameters. The optional ones are set to `None`, and the flags to `false`."
+    )?;
+    if !required.is_empty() {
+        writeln!(file, "        /// # Arguments")?;
+    }
+    for param in required.iter() {
+        writeln!(
+            file,
+            "        /// * `{}` - {}",
+            rustifier::parameters::attr_name(param),
+            param.description.replace('\n', "\n        /// ")
+        )?;
+    }
+    write!(file, "        pub fn new(")?;
+    for (i, param) in required.iter().enumerate() {
+        if i > 0 {
+            write!(file, ", ")?;
+        }
+        write!(
+            file,
+            "{}: {}",
+            rustifier::parameters::attr_name(param),
+            rustifier::parameters::qual_name(param)
+        )?;
+    }
+    writeln!(file, ") -> Self {{")?;
+    write!(
+        file,
+        "            {} {{",
+        rustifier::definitions::type_name(def)
+    )?;
+    for param in params.iter() {
+        if rustifier::parameters::is_optional(param) {
+            write!(file, " {}: None,", rustifier::parameters::attr_name(param))?;
+        } else if rustifier::parameters::is_flag(param) {
+            write!(file, " {}: false,", rustifier::parameters::attr_name(param))?;
+        } else {
+            write!(file, " {},", rustifier::parameters::attr_name(param))?;
+        }
+    }
+    writeln!(file, " }}")?;
+    writeln!(file, "        }}")?;
+    Ok(())
+}
 
 /// Defines the `function` corresponding to the definition:
 ///
@@ -79,59 +338,131 @@ fn write_function<W: Write>(
 
     writeln!(
         file,
-        "client_id: i32) -> Result<{}, crate::types::Error> {{",
+        "client_id: i32) -> Result<{}, crate::Error> {{",
         rustifier::types::qual_name(&def.ty, false)
     )?;
 
-    // Compose request
-    writeln!(file, "        let request = json!({{")?;
-    writeln!(file, "            \"@type\": \"{}\",", def.name)?;
-    for param in def.params.iter() {
-        if rustifier::parameters::is_for_bots_only(param) && !gen_bots_only_api {
-            continue;
-        }
+    // Send request
+    write!(file, "        crate::send_function(client_id, ")?;
+    write_request(file, def, "", gen_bots_only_api)?;
+    writeln!(file, ").await")?;
+
+    writeln!(file, "    }}")?;
+    Ok(())
+}
+
+/// Defines the blocking `function` corresponding to a definition that can be
+/// executed synchronously:
+///
+/// ```ignore
+/// pub fn name(field: Type) -> Result {
+///
+/// }
+/// ```
+fn write_execute_function<W: Write>(
+    file: &mut W,
+    def: &Definition,
+    gen_bots_only_api: bool,
+) -> io::Result<()> {
+    if rustifier::definitions::is_for_bots_only(def) && !gen_bots_only_api {
+        return Ok(());
+    }
+
+    let params = def
+        .params
+        .iter()
+        .filter(|p| !rustifier::parameters::is_for_bots_only(p) || gen_bots_only_api)
+        .collect::<Vec<_>>();
 
+    // Documentation
+    writeln!(
+        file,
+        "{}",
+        rustifier::definitions::description(def, "        ")
+    )?;
+    if !params.is_empty() {
+        writeln!(file, "        /// # Arguments")?;
+    }
+    for param in params.iter() {
         writeln!(
             file,
-            "            \"{0}\": {1},",
-            param.name,
+            "        /// * `{}` - {}",
             rustifier::parameters::attr_name(param),
+            param.description.replace('\n', "\n        /// ")
         )?;
     }
-    writeln!(file, "        }});")?;
 
-    // Send request
-    writeln!(
+    // Function
+    writeln!(file, "        #[allow(clippy::too_many_arguments)]")?;
+    write!(
         file,
-        "        let response = send_request(client_id, request).await;"
+        "        pub fn {}(",
+        rustifier::definitions::function_name(def)
     )?;
-    writeln!(file, "        if response[\"@type\"] == \"error\" {{")?;
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            write!(file, ", ")?;
+        }
+        write!(file, "{}: ", rustifier::parameters::attr_name(param))?;
+
+        let is_optional = rustifier::parameters::is_optional(param);
+        if is_optional {
+            write!(file, "Option<")?;
+        }
+        write!(file, "{}", rustifier::parameters::qual_name(param))?;
+        if is_optional {
+            write!(file, ">")?;
+        }
+    }
     writeln!(
         file,
-        "            return Err(serde_json::from_value(response).unwrap())"
+        ") -> Result<{}, crate::Error> {{",
+        rustifier::types::qual_name(&def.ty, false)
     )?;
+
+    // Execute request
+    write!(file, "            crate::execute_function(")?;
+    write_request(file, def, "super::", gen_bots_only_api)?;
+    writeln!(file, ")")?;
+
     writeln!(file, "        }}")?;
+    Ok(())
+}
 
-    if rustifier::types::is_ok(&def.ty) {
-        writeln!(file, "        Ok(())")?;
-    } else {
-        writeln!(
-            file,
-            "        Ok(serde_json::from_value(response).unwrap())"
-        )?;
-    }
+/// Writes the expression building the request `struct` corresponding to the
+/// definition out of the parameters of the function, prefixing its name with
+/// `path`.
+pub(crate) fn write_request<W: Write>(
+    file: &mut W,
+    def: &Definition,
+    path: &str,
+    gen_bots_only_api: bool,
+) -> io::Result<()> {
+    write!(
+        file,
+        "{}{} {{",
+        path,
+        rustifier::definitions::type_name(def)
+    )?;
+    for param in def.params.iter() {
+        if rustifier::parameters::is_for_bots_only(param) && !gen_bots_only_api {
+            continue;
+        }
 
-    writeln!(file, "    }}")?;
+        write!(file, " {},", rustifier::parameters::attr_name(param))?;
+    }
+    write!(file, " }}")?;
     Ok(())
 }
 
-/// Writes an entire definition as Rust code (`fn`).
+/// Writes an entire definition as Rust code (`struct` and `fn`).
 fn write_definition<W: Write>(
     file: &mut W,
     def: &Definition,
     metadata: &Metadata,
     gen_bots_only_api: bool,
 ) -> io::Result<()> {
+    write_struct(file, def, gen_bots_only_api)?;
     write_function(file, def, metadata, gen_bots_only_api)?;
     Ok(())
 }
@@ -145,17 +476,26 @@ pub(crate) fn write_functions_mod<W: Write>(
 ) -> io::Result<()> {
     // Begin outermost mod
     writeln!(file, "pub mod functions {{")?;
-    writeln!(file, "    use serde_json::json;")?;
-    writeln!(file, "    use crate::send_request;")?;
+    writeln!(file, "    use serde::{{Deserialize, Serialize}};")?;
+    writeln!(file, "    use serde_with::{{serde_as, DisplayFromStr}};")?;
 
     let functions = definitions
         .iter()
         .filter(|d| d.category == Category::Functions);
 
-    for definition in functions {
+    for definition in functions.clone() {
         write_definition(&mut file, definition, metadata, gen_bots_only_api)?;
     }
 
+    // Begin mod of the functions that can be executed synchronously
+    writeln!(file, "    pub mod execute {{")?;
+
+    for definition in functions.filter(|d| rustifier::definitions::is_synchronous(d)) {
+        write_execute_function(&mut file, definition, gen_bots_only_api)?;
+    }
+
+    writeln!(file, "    }}")?;
+
     // End outermost mod
     writeln!(file, "}}")
 }
@@ -0,0 +1,222 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Code to generate the `UpdateHandler` trait from the variants of `Update`.
+
+use crate::metadata::Metadata;
+use crate::rustifier;
+use std::io::{self, Write};
+use tdlib_tl_parser::tl::{Category, Definition};
+
+const FUTURE: &str = "impl std::future::Future<Output = ()> + Send";
+
+const TRAIT_DOCS: &str = "\
+/// Handle the updates of TdLib with a method per kind of update, each doing
+/// nothing by default.
+///
+/// The updates are passed to the right method by `dispatch_update`. Handlers
+/// can be combined into tuples, `(a, b)` calling the method of `a` and then
+/// the one of `b`, and shared with references and `Arc`s.
+///
+/// # Examples
+///
+/// ```no_run
+/// use futures_util::StreamExt;
+/// use tdlib::{dispatch_update, types::UpdateNewMessage, Client, UpdateHandler};
+///
+/// struct Printer;
+///
+/// impl UpdateHandler for Printer {
+///     async fn on_new_message(&self, update: &UpdateNewMessage) {
+///         println!(\"New message in chat {}\", update.message.chat_id);
+///     }
+/// }
+///
+/// # async fn example(client: Client) {
+/// let mut updates = client.updates();
+/// while let Some(update) = updates.next().await {
+///     dispatch_update(&Printer, &update).await;
+/// }
+/// # }
+/// ```";
+
+/// A method of the handler, with the type of its argument, if any, and the
+/// variant of `Update` it handles.
+struct Method {
+    name: String,
+    argument: Option<String>,
+    variant: String,
+    description: String,
+}
+
+fn methods(
+    definitions: &[Definition],
+    metadata: &Metadata,
+    gen_bots_only_api: bool,
+    gen_unknown_variants: bool,
+) -> Vec<Method> {
+    let update = &definitions
+        .iter()
+        .find(|d| d.category == Category::Types && d.ty.name == "Update")
+        .expect("the API has updates")
+        .ty;
+
+    let mut methods = metadata
+        .defs_with_type(update)
+        .iter()
+        .filter(|d| !rustifier::definitions::is_for_bots_only(d) || gen_bots_only_api)
+        .map(|d| Method {
+            name: rustifier::definitions::handler_name(d),
+            argument: (!d.params.is_empty()).then(|| rustifier::definitions::qual_name(d)),
+            variant: rustifier::definitions::variant_name(d),
+            description: rustifier::definitions::description(d, "    "),
+        })
+        .collect::<Vec<_>>();
+
+    if gen_unknown_variants {
+        methods.push(Method {
+            name: "on_unrecognized".into(),
+            argument: Some("serde_json::Value".into()),
+            variant: "Unrecognized".into(),
+            description: "    /// An update unknown to this version of the crate, kept as raw JSON"
+                .into(),
+        });
+    }
+
+    methods
+}
+
+/// Write the signature of a method, up to the opening brace.
+fn write_signature<W: Write>(file: &mut W, method: &Method, is_async: bool) -> io::Result<()> {
+    let prefix = if is_async { "async " } else { "" };
+    write!(file, "    {}fn {}(&self", prefix, method.name)?;
+    if let Some(argument) = &method.argument {
+        write!(file, ", update: &{}", argument)?;
+    }
+    if is_async {
+        writeln!(file, ") {{")
+    } else {
+        writeln!(file, ") -> {} {{", FUTURE)
+    }
+}
+
+/// The arguments of a call to a method.
+fn call_arguments(method: &Method) -> &'static str {
+    if method.argument.is_some() {
+        "update"
+    } else {
+        ""
+    }
+}
+
+/// Write the trait itself, whose methods do nothing by default.
+fn write_trait<W: Write>(file: &mut W, methods: &[Method]) -> io::Result<()> {
+    writeln!(file, "{}", TRAIT_DOCS)?;
+    writeln!(file, "pub trait UpdateHandler: Send + Sync {{")?;
+    for method in methods {
+        writeln!(file, "{}", method.description)?;
+        write_signature(file, method, false)?;
+        if method.argument.is_some() {
+            writeln!(file, "        let _ = update;")?;
+        }
+        writeln!(file, "        async {{}}")?;
+        writeln!(file, "    }}")?;
+    }
+    writeln!(file, "}}")
+}
+
+/// Write the implementations forwarding to the handler behind a pointer.
+fn write_pointer_impls<W: Write>(file: &mut W, methods: &[Method]) -> io::Result<()> {
+    for ty in ["&H", "std::sync::Arc<H>"] {
+        writeln!(file, "impl<H: UpdateHandler> UpdateHandler for {} {{", ty)?;
+        for method in methods {
+            write_signature(file, method, false)?;
+            writeln!(
+                file,
+                "        (**self).{}({})",
+                method.name,
+                call_arguments(method)
+            )?;
+            writeln!(file, "    }}")?;
+        }
+        writeln!(file, "}}")?;
+    }
+    Ok(())
+}
+
+/// Write the implementation for pairs of handlers, calling both in order.
+fn write_tuple_impl<W: Write>(file: &mut W, methods: &[Method]) -> io::Result<()> {
+    writeln!(
+        file,
+        "impl<A: UpdateHandler, B: UpdateHandler> UpdateHandler for (A, B) {{"
+    )?;
+    for method in methods {
+        write_signature(file, method, true)?;
+        for field in 0..2 {
+            writeln!(
+                file,
+                "        self.{}.{}({}).await;",
+                field,
+                method.name,
+                call_arguments(method)
+            )?;
+        }
+        writeln!(file, "    }}")?;
+    }
+    writeln!(file, "}}")
+}
+
+/// Write the function calling the method corresponding to an update.
+fn write_dispatch<W: Write>(file: &mut W, methods: &[Method]) -> io::Result<()> {
+    writeln!(
+        file,
+        "/// Call the method of the handler corresponding to the update."
+    )?;
+    writeln!(
+        file,
+        "pub async fn dispatch_update<H: UpdateHandler>(handler: &H, update: &crate::enums::Update) {{"
+    )?;
+    writeln!(file, "    match update {{")?;
+    for method in methods {
+        if method.argument.is_some() {
+            writeln!(
+                file,
+                "        crate::enums::Update::{}(update) => handler.{}(update).await,",
+                method.variant, method.name
+            )?;
+        } else {
+            writeln!(
+                file,
+                "        crate::enums::Update::{} => handler.{}().await,",
+                method.variant, method.name
+            )?;
+        }
+    }
+    writeln!(file, "    }}")?;
+    writeln!(file, "}}")
+}
+
+/// Write the `UpdateHandler` trait, its implementations and `dispatch_update`.
+pub(crate) fn write_update_handler<W: Write>(
+    file: &mut W,
+    definitions: &[Definition],
+    metadata: &Metadata,
+    gen_bots_only_api: bool,
+    gen_unknown_variants: bool,
+) -> io::Result<()> {
+    let methods = methods(
+        definitions,
+        metadata,
+        gen_bots_only_api,
+        gen_unknown_variants,
+    );
+    write_trait(file, &methods)?;
+    write_pointer_impls(file, &methods)?;
+    write_tuple_impl(file, &methods)?;
+    write_dispatch(file, &methods)
+}
@@ -9,8 +9,10 @@
 
 //! This module gathers all the code generation submodules and coordinates
 //! them, feeding them the right data.
+mod client;
 mod enums;
 mod functions;
+mod handler;
 mod metadata;
 mod rustifier;
 mod types;
@@ -30,6 +32,7 @@ pub fn generate_rust_code(
     file: &mut impl Write,
     definitions: &[Definition],
     gen_bots_only_api: bool,
+    gen_unknown_variants: bool,
 ) -> io::Result<()> {
     write!(
         file,
@@ -47,8 +50,22 @@ pub fn generate_rust_code(
 
     let metadata = metadata::Metadata::new(definitions);
     types::write_types_mod(file, definitions, &metadata, gen_bots_only_api)?;
-    enums::write_enums_mod(file, definitions, &metadata, gen_bots_only_api)?;
+    enums::write_enums_mod(
+        file,
+        definitions,
+        &metadata,
+        gen_bots_only_api,
+        gen_unknown_variants,
+    )?;
     functions::write_functions_mod(file, definitions, &metadata, gen_bots_only_api)?;
+    client::write_client_impl(file, definitions, &metadata, gen_bots_only_api)?;
+    handler::write_update_handler(
+        file,
+        definitions,
+        &metadata,
+        gen_bots_only_api,
+        gen_unknown_variants,
+    )?;
 
     Ok(())
 }
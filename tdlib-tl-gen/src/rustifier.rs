@@ -146,6 +146,23 @@ pub mod definitions {
         .to_string()
     }
 
+    /// The name of the method of `UpdateHandler` handling an update, like
+    /// `on_new_message` for `updateNewMessage`.
+    pub fn handler_name(def: &Definition) -> String {
+        let mut result = String::from("on");
+
+        variant_name(def).chars().for_each(|c| {
+            if c.is_ascii_uppercase() {
+                result.push('_');
+                result.push(c.to_ascii_lowercase());
+            } else {
+                result.push(c);
+            }
+        });
+
+        result
+    }
+
     pub fn description(def: &Definition, indent: &str) -> String {
         rusty_doc(indent, &def.description)
     }
@@ -153,6 +170,33 @@ pub mod definitions {
     pub fn is_for_bots_only(def: &Definition) -> bool {
         def.description.contains("; for bots only")
     }
+
+    pub fn is_synchronous(def: &Definition) -> bool {
+        def.description.contains("Can be called synchronously")
+    }
+
+    /// Functions named like getters which act on behalf of the user, like
+    /// sending a query to a bot or logging in to a website, or which can only
+    /// succeed once.
+    const NON_IDEMPOTENT_GETTERS: [&str; 10] = [
+        "getCallbackQueryAnswer",
+        "getChatFolderNewChats",
+        "getEmojiSuggestionsUrl",
+        "getExternalLink",
+        "getInlineQueryResults",
+        "getLoginUrl",
+        "getPassportAuthorizationForm",
+        "getPassportAuthorizationFormAvailableElements",
+        "getPaymentForm",
+        "searchChatsNearby",
+    ];
+
+    /// Whether the function only reads data, so that sending it again
+    /// doesn't have any additional effect.
+    pub fn is_idempotent(def: &Definition) -> bool {
+        (def.name.starts_with("get") || def.name.starts_with("search"))
+            && !NON_IDEMPOTENT_GETTERS.contains(&def.name.as_str())
+    }
 }
 
 pub mod types {
@@ -274,6 +318,10 @@ pub mod parameters {
         param.description.contains("; may be null") || param.description.contains("; pass null")
     }
 
+    pub fn is_flag(param: &Parameter) -> bool {
+        !is_optional(param) && qual_name(param) == "bool"
+    }
+
     pub fn is_for_bots_only(param: &Parameter) -> bool {
         param.description.contains("; for bots only")
     }
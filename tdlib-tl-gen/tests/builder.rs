@@ -0,0 +1,56 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use tdlib_tl_gen::generate_rust_code;
+use tdlib_tl_parser::parse_tl_file;
+
+const API: &str = "
+ok = Ok;
+user id:int53 = User;
+updateUser user:user = Update;
+
+---functions---
+
+//@description Sets the parameters
+//@database_directory The path to the directory for the persistent database
+//@use_test_dc Pass true to use Telegram test environment instead of the production environment
+//@use_file_database Pass true to keep information about downloaded and uploaded files
+//@system_language_code IETF language tag of the user's operating system language; may be null
+setParameters database_directory:string use_test_dc:Bool use_file_database:Bool system_language_code:string = Ok;
+setName first:string second:string third:string fourth:string fifth:string sixth:string seventh:string eighth:string = Ok;
+";
+
+/// The code generated for the function, from its `struct` to its `Function`
+/// implementation.
+fn request(name: &str) -> String {
+    let definitions = parse_tl_file(API.to_string())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let mut code = Vec::new();
+    generate_rust_code(&mut code, &definitions, true, false).unwrap();
+    let code = String::from_utf8(code).unwrap();
+
+    let start = code.find(&format!("pub struct {} {{", name)).unwrap();
+    let end = start + code[start..].find("impl crate::Function").unwrap();
+    code[start..end].to_string()
+}
+
+#[test]
+fn flags_have_setters() {
+    let code = request("SetParameters");
+    assert!(code.contains("pub fn new(database_directory: String) -> Self {"));
+    assert!(code.contains("use_test_dc: false, use_file_database: false,"));
+    assert!(code.contains("system_language_code: None,"));
+    assert!(code.contains("pub fn use_test_dc(mut self, use_test_dc: bool) -> Self {"));
+}
+
+#[test]
+fn no_constructor_above_max_arguments() {
+    let code = request("SetName");
+    assert!(!code.contains("pub fn new("));
+    assert!(!code.contains("too_many_arguments"));
+}
@@ -0,0 +1,47 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use tdlib_tl_gen::generate_rust_code;
+use tdlib_tl_parser::parse_tl_file;
+
+const API: &str = "
+user id:int53 first_name:string = User;
+callbackQueryAnswer text:string show_alert:Bool url:string = CallbackQueryAnswer;
+callbackQueryPayloadData data:bytes = CallbackQueryPayload;
+updateUser user:user = Update;
+
+---functions---
+
+getMe = User;
+searchUserByToken token:string = User;
+getCallbackQueryAnswer chat_id:int53 message_id:int53 payload:CallbackQueryPayload = CallbackQueryAnswer;
+";
+
+/// The `Function` implementation generated for a function.
+fn function_impl(code: &str, name: &str) -> String {
+    let start = code
+        .find(&format!("impl crate::Function for {} {{", name))
+        .unwrap();
+    let end = start + code[start..].find("fn parse_response").unwrap();
+    code[start..end].to_string()
+}
+
+#[test]
+fn only_reads_are_idempotent() {
+    let definitions = parse_tl_file(API.to_string())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let mut code = Vec::new();
+    generate_rust_code(&mut code, &definitions, true, false).unwrap();
+    let code = String::from_utf8(code).unwrap();
+
+    let idempotent = "const IDEMPOTENT: bool = true;";
+    assert!(function_impl(&code, "GetMe").contains(idempotent));
+    assert!(function_impl(&code, "SearchUserByToken").contains(idempotent));
+    // It sends a callback query to the bot, which would get it twice
+    assert!(!function_impl(&code, "GetCallbackQueryAnswer").contains(idempotent));
+}
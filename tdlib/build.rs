@@ -34,8 +34,9 @@ fn load_tl(file: &str) -> io::Result<Vec<Definition>> {
 }
 
 fn main() -> std::io::Result<()> {
-    // Prevent linking libraries to avoid documentation failure
-    #[cfg(not(feature = "dox"))]
+    // Prevent linking libraries to avoid documentation failure, or when the
+    // library is loaded at runtime
+    #[cfg(not(any(feature = "dox", feature = "dlopen")))]
     system_deps::Config::new().probe().unwrap();
 
     let definitions = load_tl("tl/api.tl")?;
@@ -44,7 +45,12 @@ fn main() -> std::io::Result<()> {
         Path::new(&env::var("OUT_DIR").unwrap()).join("generated.rs"),
     )?);
 
-    generate_rust_code(&mut file, &definitions, cfg!(feature = "bots-only-api"))?;
+    generate_rust_code(
+        &mut file,
+        &definitions,
+        cfg!(feature = "bots-only-api"),
+        cfg!(feature = "unknown-variants"),
+    )?;
 
     file.flush()?;
     Ok(())
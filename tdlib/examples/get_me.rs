@@ -1,12 +1,11 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
+use futures_util::StreamExt;
 use tdlib::{
+    auth::{self, Authenticator, Login},
     enums::{AuthorizationState, Update, User},
-    functions,
+    functions::SetTdlibParameters,
+    types::{AuthorizationStateWaitCode, AuthorizationStateWaitPassword},
+    Client, Error,
 };
-use tokio::sync::mpsc::{self, Receiver, Sender};
 
 fn ask_user(string: &str) -> String {
     println!("{}", string);
@@ -15,122 +14,85 @@ fn ask_user(string: &str) -> String {
     input.trim().to_string()
 }
 
-async fn handle_update(update: Update, auth_tx: &Sender<AuthorizationState>) {
-    match update {
-        Update::AuthorizationState(update) => {
-            auth_tx.send(update.authorization_state).await.unwrap();
-        }
-        _ => (),
+struct TerminalAuthenticator;
+
+impl Authenticator for TerminalAuthenticator {
+    async fn login(&self) -> Option<Login> {
+        let input = ask_user("Enter your phone number (include the country calling code):");
+        Some(Login::PhoneNumber(input))
     }
-}
 
-async fn handle_authorization_state(
-    client_id: i32,
-    mut auth_rx: Receiver<AuthorizationState>,
-    run_flag: Arc<AtomicBool>,
-) -> Receiver<AuthorizationState> {
-    while let Some(state) = auth_rx.recv().await {
-        match state {
-            AuthorizationState::WaitTdlibParameters => {
-                let response = functions::set_tdlib_parameters(
-                    false,
-                    "get_me_db".into(),
-                    String::new(),
-                    String::new(),
-                    false,
-                    false,
-                    false,
-                    false,
-                    env!("API_ID").parse().unwrap(),
-                    env!("API_HASH").into(),
-                    "en".into(),
-                    "Desktop".into(),
-                    String::new(),
-                    env!("CARGO_PKG_VERSION").into(),
-                    false,
-                    true,
-                    client_id,
-                )
-                .await;
+    async fn code(&self, _: &AuthorizationStateWaitCode) -> Option<String> {
+        Some(ask_user("Enter the verification code:"))
+    }
 
-                if let Err(error) = response {
-                    println!("{}", error.message);
-                }
-            }
-            AuthorizationState::WaitPhoneNumber => loop {
-                let input = ask_user("Enter your phone number (include the country calling code):");
-                let response =
-                    functions::set_authentication_phone_number(input, None, client_id).await;
-                match response {
-                    Ok(_) => break,
-                    Err(e) => println!("{}", e.message),
-                }
-            },
-            AuthorizationState::WaitCode(_) => loop {
-                let input = ask_user("Enter the verification code:");
-                let response = functions::check_authentication_code(input, client_id).await;
-                match response {
-                    Ok(_) => break,
-                    Err(e) => println!("{}", e.message),
-                }
-            },
-            AuthorizationState::Ready => {
-                break;
-            }
-            AuthorizationState::Closed => {
-                // Set the flag to false to stop receiving updates from the
-                // spawned task
-                run_flag.store(false, Ordering::Release);
-                break;
-            }
-            _ => (),
-        }
+    async fn password(&self, state: &AuthorizationStateWaitPassword) -> Option<String> {
+        let question = format!("Enter the password (hint: {}):", state.password_hint);
+        Some(ask_user(&question))
     }
 
-    auth_rx
+    async fn error(&self, error: &Error) {
+        println!("{}", error);
+    }
 }
 
 #[tokio::main]
 async fn main() {
     // Create the client object
-    let client_id = tdlib::create_client();
-
-    // Create a mpsc channel for handling AuthorizationState updates separately
-    // from the task
-    let (auth_tx, auth_rx) = mpsc::channel(5);
-
-    // Create a flag to make it possible to stop receiving updates
-    let run_flag = Arc::new(AtomicBool::new(true));
-    let run_flag_clone = run_flag.clone();
+    let client = Client::new();
 
-    // Spawn a task to receive updates/responses
+    // Spawn a task to handle the updates of the client. The stream ends once
+    // the client is closed.
+    let mut updates = client.updates();
     let handle = tokio::spawn(async move {
-        while run_flag_clone.load(Ordering::Acquire) {
-            if let Some((update, _client_id)) = tdlib::receive() {
-                handle_update(update, &auth_tx).await;
+        while let Some(update) = updates.next().await {
+            if let Update::AuthorizationState(update) = update {
+                if let AuthorizationState::Closed = update.authorization_state {
+                    println!("The client is closed");
+                }
             }
         }
     });
 
-    // Set a fairly low verbosity level. We mainly do this because tdlib
-    // requires to perform a random request with the client to start receiving
-    // updates for it.
-    functions::set_log_verbosity_level(2, client_id)
+    // Set a fairly low verbosity level
+    client.set_log_verbosity_level(2).await.unwrap();
+
+    // Authenticate the client
+    // Use named fields, so that changes to the parameters in new TdLib
+    // versions break at compile time
+    let parameters = SetTdlibParameters {
+        use_test_dc: false,
+        database_directory: "get_me_db".into(),
+        files_directory: String::new(),
+        database_encryption_key: String::new(),
+        use_file_database: false,
+        use_chat_info_database: false,
+        use_message_database: false,
+        use_secret_chats: false,
+        api_id: env!("API_ID").parse().unwrap(),
+        api_hash: env!("API_HASH").into(),
+        system_language_code: "en".into(),
+        device_model: "Desktop".into(),
+        system_version: String::new(),
+        application_version: env!("CARGO_PKG_VERSION").into(),
+        enable_storage_optimizer: false,
+        ignore_file_names: true,
+    };
+    auth::authorize(&client, &parameters, &TerminalAuthenticator)
         .await
         .unwrap();
 
-    // Handle the authorization state to authenticate the client
-    let auth_rx = handle_authorization_state(client_id, auth_rx, run_flag.clone()).await;
-
-    // Run the get_me() method to get user information
-    let User::User(me) = functions::get_me(client_id).await.unwrap();
+    // Run the get_me() method to get user information. The pattern is
+    // refutable with the `unknown-variants` feature, which adds a variant for
+    // the types unknown to the crate to every enum.
+    #[allow(irrefutable_let_patterns)]
+    let User::User(me) = client.get_me().await.unwrap() else {
+        panic!("Unknown user type");
+    };
     println!("Hi, I'm {}", me.first_name);
 
     // Tell the client to close
-    functions::close(client_id).await.unwrap();
-
-    // Handle the authorization state to wait for the "Closed" state
-    handle_authorization_state(client_id, auth_rx, run_flag.clone()).await;
+    client.close().await.unwrap();
 
     // Wait for the previously spawned task to end the execution
     handle.await.unwrap();
@@ -0,0 +1,512 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Drive a client through the authorization states of TdLib.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), tdlib::auth::AuthError> {
+//! use tdlib::auth::{authorize, tdlib_parameters, Authenticator, Login};
+//! use tdlib::Client;
+//!
+//! struct Bot;
+//!
+//! impl Authenticator for Bot {
+//!     async fn login(&self) -> Option<Login> {
+//!         Some(Login::BotToken("123456:ABC-DEF".into()))
+//!     }
+//! }
+//!
+//! let client = Client::new();
+//! let parameters = tdlib_parameters(
+//!     94575,
+//!     "a3406de8d171bb422bb6ddf3bbd800e2".into(),
+//!     "1.0".into(),
+//! );
+//! authorize(&client, &parameters, &Bot).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::enums::{AuthorizationState, EmailAddressAuthentication, Update};
+use crate::functions::{
+    CheckAuthenticationBotToken, CheckAuthenticationCode, CheckAuthenticationEmailCode,
+    CheckAuthenticationPassword, RegisterUser, RequestQrCodeAuthentication,
+    SetAuthenticationEmailAddress, SetAuthenticationPhoneNumber, SetTdlibParameters,
+};
+use crate::types::{
+    AuthorizationStateWaitCode, AuthorizationStateWaitEmailAddress,
+    AuthorizationStateWaitEmailCode, AuthorizationStateWaitPassword,
+    AuthorizationStateWaitRegistration,
+};
+use crate::{Client, Error, Function, Updates};
+use futures_util::{FutureExt, StreamExt};
+use std::fmt;
+use std::future::Future;
+
+/// The parameters sent to TdLib with `setTdlibParameters`, storing the
+/// database in the `tdlib` directory and with every other parameter set to a
+/// sensible default. The fields can be changed before authorizing.
+pub fn tdlib_parameters(
+    api_id: i32,
+    api_hash: String,
+    application_version: String,
+) -> SetTdlibParameters {
+    SetTdlibParameters {
+        use_test_dc: false,
+        database_directory: "tdlib".into(),
+        files_directory: String::new(),
+        database_encryption_key: String::new(),
+        use_file_database: true,
+        use_chat_info_database: true,
+        use_message_database: true,
+        use_secret_chats: false,
+        api_id,
+        api_hash,
+        system_language_code: "en".into(),
+        device_model: "Desktop".into(),
+        system_version: String::new(),
+        application_version,
+        enable_storage_optimizer: true,
+        ignore_file_names: false,
+    }
+}
+
+/// How to log in when TdLib asks for a phone number.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Login {
+    /// Log in as a user with the given phone number.
+    PhoneNumber(String),
+    /// Log in as a bot with the given token.
+    BotToken(String),
+    /// Log in as a user by scanning a QR code from another logged in device.
+    /// The link to encode in the QR code is passed to
+    /// `Authenticator::qr_link`.
+    QrCode,
+}
+
+/// Name of a new user, sent when TdLib asks to register the account.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Registration {
+    pub first_name: String,
+    pub last_name: String,
+}
+
+/// The source of the data needed to authorize a client, usually the user.
+///
+/// Every method returns `None` to abort the authorization, which is the
+/// default for everything but `login`, so an authenticator only needs to
+/// implement the steps it supports.
+pub trait Authenticator: Send + Sync {
+    /// How to log in.
+    fn login(&self) -> impl Future<Output = Option<Login>> + Send;
+
+    /// The code sent to the user to confirm the phone number.
+    fn code(
+        &self,
+        state: &AuthorizationStateWaitCode,
+    ) -> impl Future<Output = Option<String>> + Send {
+        let _ = state;
+        async { None }
+    }
+
+    /// The password of the account.
+    fn password(
+        &self,
+        state: &AuthorizationStateWaitPassword,
+    ) -> impl Future<Output = Option<String>> + Send {
+        let _ = state;
+        async { None }
+    }
+
+    /// The email address of the user.
+    fn email_address(
+        &self,
+        state: &AuthorizationStateWaitEmailAddress,
+    ) -> impl Future<Output = Option<String>> + Send {
+        let _ = state;
+        async { None }
+    }
+
+    /// The code sent to the email address of the user, or a token of an
+    /// external account.
+    fn email_code(
+        &self,
+        state: &AuthorizationStateWaitEmailCode,
+    ) -> impl Future<Output = Option<EmailAddressAuthentication>> + Send {
+        let _ = state;
+        async { None }
+    }
+
+    /// The name of the user, when the phone number isn't registered yet.
+    /// The user must accept the terms of service in the state.
+    fn registration(
+        &self,
+        state: &AuthorizationStateWaitRegistration,
+    ) -> impl Future<Output = Option<Registration>> + Send {
+        let _ = state;
+        async { None }
+    }
+
+    /// Show the link to confirm the login from another device, usually as a
+    /// QR code. It may be called again with a new link.
+    fn qr_link(&self, link: &str) -> impl Future<Output = ()> + Send {
+        let _ = link;
+        async {}
+    }
+
+    /// Report an error caused by the data provided by the authenticator,
+    /// like an invalid code. The same step is then asked again.
+    fn error(&self, error: &Error) -> impl Future<Output = ()> + Send {
+        log::warn!("Authorization failed: {}", error);
+        async {}
+    }
+}
+
+/// An error that occurred while authorizing a client.
+#[derive(Debug)]
+pub enum AuthError {
+    /// The authenticator gave up in the given state.
+    Aborted(AuthorizationState),
+    /// The client was closed, or started logging out, before being
+    /// authorized.
+    Closed,
+    /// A request failed for reasons not caused by the authenticator.
+    Request(Error),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Aborted(_) => write!(f, "the authorization was aborted"),
+            AuthError::Closed => write!(f, "the client was closed before being authorized"),
+            AuthError::Request(error) => write!(f, "the authorization failed: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AuthError::Request(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for AuthError {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Closed => AuthError::Closed,
+            error => AuthError::Request(error),
+        }
+    }
+}
+
+/// The result of handling an authorization state.
+enum Step {
+    /// Wait for the next state.
+    Next,
+    /// The request failed because of the authenticator, ask again.
+    Retry(Error),
+    /// The client is authorized.
+    Done,
+}
+
+/// Authorize the client, answering the requests of TdLib with `parameters`
+/// and the data provided by `authenticator`. It resolves once the client is
+/// ready, or with an error if the client gets closed in the meantime.
+///
+/// If the client is already authorized, this returns immediately.
+pub async fn authorize<A: Authenticator>(
+    client: &Client,
+    parameters: &SetTdlibParameters,
+    authenticator: &A,
+) -> Result<(), AuthError> {
+    let mut updates = client.updates();
+    let mut state = client.get_authorization_state().await?;
+    // The updates are dispatched in order with the responses, so the states
+    // received before the current one are older, and must not be taken for
+    // the ones following it
+    while let Some(Some(_)) = updates.next().now_or_never() {}
+
+    loop {
+        match step(client, parameters, authenticator, &state).await? {
+            Step::Done => return Ok(()),
+            Step::Retry(error) => authenticator.error(&error).await,
+            Step::Next => state = next_state(&mut updates, &state).await?,
+        }
+    }
+}
+
+/// Wait for an authorization state different from the current one.
+async fn next_state(
+    updates: &mut Updates,
+    current: &AuthorizationState,
+) -> Result<AuthorizationState, AuthError> {
+    while let Some(update) = updates.next().await {
+        if let Update::AuthorizationState(update) = update {
+            if update.authorization_state != *current {
+                return Ok(update.authorization_state);
+            }
+        }
+    }
+    Err(AuthError::Closed)
+}
+
+/// Send a request with the data of the authenticator, distinguishing the
+/// errors caused by bad data from the others.
+async fn submit<F: Function>(client: &Client, function: F) -> Result<Step, AuthError> {
+    match client.send(function).await {
+        Ok(_) => Ok(Step::Next),
+        Err(Error::Tdlib(error)) if error.code == 400 => Ok(Step::Retry(Error::Tdlib(error))),
+        Err(error) => Err(error.into()),
+    }
+}
+
+async fn step<A: Authenticator>(
+    client: &Client,
+    parameters: &SetTdlibParameters,
+    authenticator: &A,
+    state: &AuthorizationState,
+) -> Result<Step, AuthError> {
+    let aborted = || AuthError::Aborted(state.clone());
+
+    match state {
+        AuthorizationState::WaitTdlibParameters => {
+            client.send(parameters.clone()).await?;
+            Ok(Step::Next)
+        }
+        AuthorizationState::WaitPhoneNumber => match authenticator.login().await {
+            Some(Login::PhoneNumber(phone_number)) => {
+                submit(client, SetAuthenticationPhoneNumber::new(phone_number)).await
+            }
+            Some(Login::BotToken(token)) => {
+                submit(client, CheckAuthenticationBotToken::new(token)).await
+            }
+            Some(Login::QrCode) => {
+                submit(client, RequestQrCodeAuthentication::new(Vec::new())).await
+            }
+            None => Err(aborted()),
+        },
+        AuthorizationState::WaitEmailAddress(state) => {
+            let email_address = authenticator
+                .email_address(state)
+                .await
+                .ok_or_else(aborted)?;
+            submit(client, SetAuthenticationEmailAddress::new(email_address)).await
+        }
+        AuthorizationState::WaitEmailCode(state) => {
+            let code = authenticator.email_code(state).await.ok_or_else(aborted)?;
+            submit(client, CheckAuthenticationEmailCode::new(code)).await
+        }
+        AuthorizationState::WaitCode(state) => {
+            let code = authenticator.code(state).await.ok_or_else(aborted)?;
+            submit(client, CheckAuthenticationCode::new(code)).await
+        }
+        AuthorizationState::WaitOtherDeviceConfirmation(state) => {
+            authenticator.qr_link(&state.link).await;
+            Ok(Step::Next)
+        }
+        AuthorizationState::WaitRegistration(state) => {
+            let registration = authenticator
+                .registration(state)
+                .await
+                .ok_or_else(aborted)?;
+            let request = RegisterUser::new(registration.first_name, registration.last_name);
+            submit(client, request).await
+        }
+        AuthorizationState::WaitPassword(state) => {
+            let password = authenticator.password(state).await.ok_or_else(aborted)?;
+            submit(client, CheckAuthenticationPassword::new(password)).await
+        }
+        AuthorizationState::Ready => Ok(Step::Done),
+        AuthorizationState::LoggingOut
+        | AuthorizationState::Closing
+        | AuthorizationState::Closed => Err(AuthError::Closed),
+        // A state added by a newer TdLib, which may be followed by a known one
+        #[cfg(feature = "unknown-variants")]
+        AuthorizationState::Unrecognized(_) => Ok(Step::Next),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBackend;
+    use crate::types::{AuthenticationCodeInfo, UpdateAuthorizationState};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    fn parameters() -> SetTdlibParameters {
+        tdlib_parameters(1, "hash".into(), "1.0".into())
+    }
+
+    fn authorization_state(state: AuthorizationState) -> Update {
+        Update::AuthorizationState(UpdateAuthorizationState {
+            authorization_state: state,
+        })
+    }
+
+    /// Answer `request_type` with `ok`, moving the clients to `next`.
+    fn transition(mock: &Arc<MockBackend>, request_type: &str, next: AuthorizationState) {
+        let weak = Arc::downgrade(mock);
+        mock.respond_with(request_type, move |_| {
+            let mock = weak.upgrade().unwrap();
+            mock.push_update(1, authorization_state(next.clone()));
+            json!({"@type": "ok"})
+        });
+    }
+
+    fn initial_state(mock: &MockBackend, state: AuthorizationState) {
+        mock.respond("getAuthorizationState", state);
+    }
+
+    struct TestAuthenticator {
+        login: Login,
+        codes: Mutex<Vec<String>>,
+        errors: Mutex<Vec<String>>,
+    }
+
+    impl TestAuthenticator {
+        fn new(login: Login, codes: &[&str]) -> Self {
+            TestAuthenticator {
+                login,
+                codes: Mutex::new(codes.iter().rev().map(|code| code.to_string()).collect()),
+                errors: Mutex::default(),
+            }
+        }
+    }
+
+    impl Authenticator for TestAuthenticator {
+        async fn login(&self) -> Option<Login> {
+            Some(self.login.clone())
+        }
+
+        async fn code(&self, _: &AuthorizationStateWaitCode) -> Option<String> {
+            self.codes.lock().unwrap().pop()
+        }
+
+        async fn error(&self, error: &Error) {
+            self.errors.lock().unwrap().push(error.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn bot_login() {
+        let mock = Arc::new(MockBackend::new());
+        initial_state(&mock, AuthorizationState::WaitTdlibParameters);
+        transition(
+            &mock,
+            "setTdlibParameters",
+            AuthorizationState::WaitPhoneNumber,
+        );
+        transition(
+            &mock,
+            "checkAuthenticationBotToken",
+            AuthorizationState::Ready,
+        );
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let authenticator = TestAuthenticator::new(Login::BotToken("token".into()), &[]);
+        authorize(&client, &parameters(), &authenticator)
+            .await
+            .unwrap();
+
+        let requests = mock.requests_of_type("checkAuthenticationBotToken");
+        assert_eq!(requests[0]["token"], "token");
+        assert_eq!(mock.requests_of_type("setTdlibParameters")[0]["api_id"], 1);
+    }
+
+    #[tokio::test]
+    async fn buffered_states_are_skipped() {
+        let mock = Arc::new(MockBackend::new());
+        // A state received before the response, which is older than it
+        let weak = Arc::downgrade(&mock);
+        mock.respond_with("getAuthorizationState", move |_| {
+            let mock = weak.upgrade().unwrap();
+            mock.push_update(
+                1,
+                authorization_state(AuthorizationState::WaitTdlibParameters),
+            );
+            serde_json::to_value(AuthorizationState::WaitPhoneNumber).unwrap()
+        });
+        transition(
+            &mock,
+            "checkAuthenticationBotToken",
+            AuthorizationState::Ready,
+        );
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let authenticator = TestAuthenticator::new(Login::BotToken("token".into()), &[]);
+        authorize(&client, &parameters(), &authenticator)
+            .await
+            .unwrap();
+
+        assert!(mock.requests_of_type("setTdlibParameters").is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_code_is_asked_again() {
+        let mock = Arc::new(MockBackend::new());
+        let code_info = AuthenticationCodeInfo {
+            phone_number: "+1234".into(),
+            r#type: crate::enums::AuthenticationCodeType::Sms(
+                crate::types::AuthenticationCodeTypeSms { length: 5 },
+            ),
+            next_type: None,
+            timeout: 60,
+        };
+        initial_state(&mock, AuthorizationState::WaitPhoneNumber);
+        transition(
+            &mock,
+            "setAuthenticationPhoneNumber",
+            AuthorizationState::WaitCode(AuthorizationStateWaitCode { code_info }),
+        );
+        let weak = Arc::downgrade(&mock);
+        mock.respond_with("checkAuthenticationCode", move |request: &Value| {
+            if request["code"] != "12345" {
+                return json!({"@type": "error", "code": 400, "message": "PHONE_CODE_INVALID"});
+            }
+            let mock = weak.upgrade().unwrap();
+            mock.push_update(1, authorization_state(AuthorizationState::Ready));
+            json!({"@type": "ok"})
+        });
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let authenticator =
+            TestAuthenticator::new(Login::PhoneNumber("+1234".into()), &["0000", "12345"]);
+        authorize(&client, &parameters(), &authenticator)
+            .await
+            .unwrap();
+
+        assert_eq!(mock.requests_of_type("checkAuthenticationCode").len(), 2);
+        assert_eq!(authenticator.errors.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn aborted_and_closed() {
+        let mock = Arc::new(MockBackend::new());
+        initial_state(&mock, AuthorizationState::WaitPassword(Default::default()));
+        let client = Client::builder().backend(mock.clone()).build();
+        let authenticator = TestAuthenticator::new(Login::QrCode, &[]);
+        assert!(matches!(
+            authorize(&client, &parameters(), &authenticator).await,
+            Err(AuthError::Aborted(AuthorizationState::WaitPassword(_)))
+        ));
+
+        let mock = Arc::new(MockBackend::new());
+        initial_state(&mock, AuthorizationState::Closing);
+        let client = Client::builder().backend(mock.clone()).build();
+        assert!(matches!(
+            authorize(&client, &parameters(), &authenticator).await,
+            Err(AuthError::Closed)
+        ));
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::tdjson;
+use std::sync::Arc;
+
+/// The interface used to talk with TdLib, mirroring the functions of the
+/// TdLib JSON interface.
+///
+/// The default implementation is `Tdjson`, which calls the real library. A
+/// different backend can be used for a `Client` with `ClientBuilder::backend`,
+/// for example to test application code without TdLib.
+pub trait Backend: Send + Sync + 'static {
+    /// Create a new client, returning its id.
+    fn create_client(&self) -> i32;
+
+    /// Send a request, serialized as JSON, to the client with the given id.
+    fn send(&self, client_id: i32, request: String);
+
+    /// Receive a response or an update, serialized as JSON, waiting at most
+    /// `timeout` seconds.
+    fn receive(&self, timeout: f64) -> Option<String>;
+
+    /// Synchronously execute a request, serialized as JSON, returning the
+    /// response.
+    fn execute(&self, request: String) -> Option<String>;
+}
+
+impl<B: Backend + ?Sized> Backend for Arc<B> {
+    fn create_client(&self) -> i32 {
+        (**self).create_client()
+    }
+
+    fn send(&self, client_id: i32, request: String) {
+        (**self).send(client_id, request)
+    }
+
+    fn receive(&self, timeout: f64) -> Option<String> {
+        (**self).receive(timeout)
+    }
+
+    fn execute(&self, request: String) -> Option<String> {
+        (**self).execute(request)
+    }
+}
+
+/// The backend calling the real TdLib through its JSON interface.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tdjson;
+
+impl Backend for Tdjson {
+    fn create_client(&self) -> i32 {
+        tdjson::create_client()
+    }
+
+    fn send(&self, client_id: i32, request: String) {
+        tdjson::send(client_id, request)
+    }
+
+    fn receive(&self, timeout: f64) -> Option<String> {
+        tdjson::receive(timeout)
+    }
+
+    fn execute(&self, request: String) -> Option<String> {
+        tdjson::execute(request)
+    }
+}
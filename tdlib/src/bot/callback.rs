@@ -0,0 +1,357 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::handler::BoxFuture;
+use crate::enums::{CallbackQueryPayload, InlineKeyboardButtonType};
+use crate::types::{
+    InlineKeyboardButton, InlineKeyboardButtonTypeCallback, UpdateNewCallbackQuery,
+    UpdateNewInlineCallbackQuery,
+};
+use crate::{Client, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The maximum length of the data of a callback button, in bytes.
+const MAX_DATA_LENGTH: usize = 64;
+
+/// A type which can be attached to a callback button and taken back from
+/// the callback queries of the button. The encoded data can't be longer than
+/// 64 bytes.
+pub trait CallbackData: Sized + Send + 'static {
+    /// Decode the data of a callback query, or return `None` if the data
+    /// isn't of this type.
+    fn decode(data: &[u8]) -> Option<Self>;
+
+    /// Encode the value as the data of a callback button.
+    fn encode(&self) -> Vec<u8>;
+}
+
+impl CallbackData for Vec<u8> {
+    fn decode(data: &[u8]) -> Option<Self> {
+        Some(data.to_vec())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl CallbackData for String {
+    fn decode(data: &[u8]) -> Option<Self> {
+        String::from_utf8(data.to_vec()).ok()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+/// Callback data encoded as JSON, for the types implementing `Serialize`
+/// and `Deserialize`.
+///
+/// # Examples
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use tdlib::bot::{CallbackData, Json};
+///
+/// #[derive(Debug, PartialEq, Deserialize, Serialize)]
+/// enum Vote {
+///     Up(i64),
+///     Down(i64),
+/// }
+///
+/// let data = Json(Vote::Up(42)).encode();
+/// assert_eq!(data, br#"{"Up":42}"#);
+/// assert_eq!(Json::<Vote>::decode(&data), Some(Json(Vote::Up(42))));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize + DeserializeOwned + Send + 'static> CallbackData for Json<T> {
+    fn decode(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice(data).ok().map(Json)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.0).expect("the callback data must be serializable")
+    }
+}
+
+/// The error of a callback button whose encoded data is longer than the 64
+/// bytes allowed by Telegram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DataTooLong {
+    /// The length of the encoded data, in bytes.
+    pub length: usize,
+}
+
+impl fmt::Display for DataTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the callback data is {} bytes long, at most {} are allowed",
+            self.length, MAX_DATA_LENGTH
+        )
+    }
+}
+
+impl std::error::Error for DataTooLong {}
+
+/// A button of an inline keyboard sending a callback query with some data
+/// when pressed. Fails if the encoded data is longer than 64 bytes.
+pub fn callback_button<T: CallbackData>(
+    text: impl Into<String>,
+    data: &T,
+) -> Result<InlineKeyboardButton, DataTooLong> {
+    let data = data.encode();
+    if data.len() > MAX_DATA_LENGTH {
+        return Err(DataTooLong { length: data.len() });
+    }
+
+    Ok(InlineKeyboardButton {
+        text: text.into(),
+        r#type: InlineKeyboardButtonType::Callback(InlineKeyboardButtonTypeCallback {
+            data: STANDARD.encode(data),
+        }),
+    })
+}
+
+/// The message with the button a callback query comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CallbackOrigin {
+    /// A message sent by the bot.
+    Message { chat_id: i64, message_id: i64 },
+    /// A message sent via the bot in inline mode.
+    Inline { inline_message_id: String },
+}
+
+/// The answer to a callback query, shown by the app of the user who pressed
+/// the button. The default answer shows nothing, and only stops the progress
+/// indicator of the button.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallbackAnswer {
+    text: String,
+    show_alert: bool,
+    url: String,
+    cache_time: i32,
+}
+
+impl CallbackAnswer {
+    /// An answer showing a text as a notification at the top of the chat.
+    pub fn notification(text: impl Into<String>) -> Self {
+        CallbackAnswer {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// An answer showing a text in an alert.
+    pub fn alert(text: impl Into<String>) -> Self {
+        CallbackAnswer {
+            text: text.into(),
+            show_alert: true,
+            ..Default::default()
+        }
+    }
+
+    /// An answer opening a URL, like the URL of a game.
+    pub fn url(url: impl Into<String>) -> Self {
+        CallbackAnswer {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the time in seconds the answer can be cached by the apps.
+    pub fn cache_time(mut self, seconds: i32) -> Self {
+        self.cache_time = seconds;
+        self
+    }
+}
+
+impl From<&str> for CallbackAnswer {
+    fn from(text: &str) -> Self {
+        CallbackAnswer::notification(text)
+    }
+}
+
+impl From<String> for CallbackAnswer {
+    fn from(text: String) -> Self {
+        CallbackAnswer::notification(text)
+    }
+}
+
+/// The callback query being handled, with the client it was received by.
+#[derive(Clone)]
+pub struct CallbackQuery {
+    client: Arc<Client>,
+    id: i64,
+    sender_user_id: i64,
+    origin: CallbackOrigin,
+    chat_instance: i64,
+    payload: CallbackQueryPayload,
+    answered: Arc<AtomicBool>,
+}
+
+impl CallbackQuery {
+    pub(crate) fn new(client: Arc<Client>, update: &UpdateNewCallbackQuery) -> Self {
+        CallbackQuery {
+            client,
+            id: update.id,
+            sender_user_id: update.sender_user_id,
+            origin: CallbackOrigin::Message {
+                chat_id: update.chat_id,
+                message_id: update.message_id,
+            },
+            chat_instance: update.chat_instance,
+            payload: update.payload.clone(),
+            answered: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn new_inline(client: Arc<Client>, update: &UpdateNewInlineCallbackQuery) -> Self {
+        CallbackQuery {
+            client,
+            id: update.id,
+            sender_user_id: update.sender_user_id,
+            origin: CallbackOrigin::Inline {
+                inline_message_id: update.inline_message_id.clone(),
+            },
+            chat_instance: update.chat_instance,
+            payload: update.payload.clone(),
+            answered: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The client of the bot.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The identifier of the query.
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// The identifier of the user who pressed the button.
+    pub fn sender_user_id(&self) -> i64 {
+        self.sender_user_id
+    }
+
+    /// The message with the button.
+    pub fn origin(&self) -> &CallbackOrigin {
+        &self.origin
+    }
+
+    /// An identifier of the chat the message with the button is in, the
+    /// same for all the queries from the chat.
+    pub fn chat_instance(&self) -> i64 {
+        self.chat_instance
+    }
+
+    /// The payload of the query.
+    pub fn payload(&self) -> &CallbackQueryPayload {
+        &self.payload
+    }
+
+    /// The data of the button, or `None` if the query is for a game.
+    pub fn data(&self) -> Option<Vec<u8>> {
+        let data = match &self.payload {
+            CallbackQueryPayload::Data(payload) => &payload.data,
+            CallbackQueryPayload::DataWithPassword(payload) => &payload.data,
+            _ => return None,
+        };
+        match STANDARD.decode(data) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                log::warn!("Failed to decode the data of a callback query: {}", e);
+                None
+            }
+        }
+    }
+
+    /// The short name of the game, if the query is for a game.
+    pub fn game_short_name(&self) -> Option<&str> {
+        match &self.payload {
+            CallbackQueryPayload::Game(payload) => Some(&payload.game_short_name),
+            _ => None,
+        }
+    }
+
+    /// Whether the query was already answered.
+    pub fn is_answered(&self) -> bool {
+        self.answered.load(Ordering::SeqCst)
+    }
+
+    /// Answer the query with `answerCallbackQuery`. A query can only be
+    /// answered once, so nothing is sent if it was already answered, by the
+    /// handler or automatically by the bot. The query can be answered again
+    /// if answering it failed.
+    pub async fn answer(&self, answer: impl Into<CallbackAnswer>) -> Result<(), Error> {
+        if self.answered.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let answer = answer.into();
+        let result = self
+            .client
+            .answer_callback_query(
+                self.id,
+                answer.text,
+                answer.show_alert,
+                answer.url,
+                answer.cache_time,
+            )
+            .await;
+        if result.is_err() {
+            self.answered.store(false, Ordering::SeqCst);
+        }
+        result
+    }
+}
+
+/// A callback query handler taking the data of the queries as a certain
+/// type, so that handlers taking different types can be stored together.
+pub(crate) trait ErasedCallbackHandler: Send + Sync {
+    /// Call the handler, returning `None` without calling it if the data
+    /// isn't of its type.
+    fn call(&self, query: CallbackQuery, data: &[u8]) -> Option<BoxFuture<'_, Result<(), Error>>>;
+}
+
+pub(crate) struct Typed<F, T> {
+    handler: F,
+    data: PhantomData<fn() -> T>,
+}
+
+impl<F, T> Typed<F, T> {
+    pub fn new(handler: F) -> Self {
+        Typed {
+            handler,
+            data: PhantomData,
+        }
+    }
+}
+
+impl<F, Fut, T> ErasedCallbackHandler for Typed<F, T>
+where
+    F: Fn(CallbackQuery, T) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    T: CallbackData,
+{
+    fn call(&self, query: CallbackQuery, data: &[u8]) -> Option<BoxFuture<'_, Result<(), Error>>> {
+        let data = T::decode(data)?;
+        Some(Box::pin((self.handler)(query, data)))
+    }
+}
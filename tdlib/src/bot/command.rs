@@ -0,0 +1,106 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// The maximum length of the name of a command, from the Bot API.
+const MAX_NAME_LENGTH: usize = 32;
+
+/// A bot command at the start of a message, like `/start` or
+/// `/help@my_bot topic`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Command<'a> {
+    /// The name of the command, without the leading `/`.
+    pub name: &'a str,
+    /// The username of the bot the command is addressed to, without the
+    /// `@`, if any.
+    pub bot_username: Option<&'a str>,
+    /// The rest of the text, after the whitespace following the command.
+    pub args: &'a str,
+}
+
+impl<'a> Command<'a> {
+    /// Parse the command a text starts with. Returns `None` if the text
+    /// doesn't start with a valid command, whose name is made of up to 32
+    /// letters, digits and underscores.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tdlib::bot::Command;
+    ///
+    /// let command = Command::parse("/weather@forecast_bot Rome  today").unwrap();
+    /// assert_eq!(command.name, "weather");
+    /// assert_eq!(command.bot_username, Some("forecast_bot"));
+    /// assert_eq!(command.args, "Rome  today");
+    /// assert!(Command::parse("not a /command").is_none());
+    /// ```
+    pub fn parse(text: &'a str) -> Option<Self> {
+        let text = text.strip_prefix('/')?;
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        let (head, args) = text.split_at(end);
+
+        let (name, bot_username) = match head.split_once('@') {
+            Some((name, bot_username)) => (name, Some(bot_username)),
+            None => (head, None),
+        };
+        let is_valid = !name.is_empty()
+            && name.len() <= MAX_NAME_LENGTH
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && bot_username != Some("");
+        is_valid.then_some(Command {
+            name,
+            bot_username,
+            args: args.trim_start(),
+        })
+    }
+
+    /// Whether the command is addressed to the bot with the given username,
+    /// either explicitly or by not naming any bot.
+    pub fn is_for(&self, bot_username: &str) -> bool {
+        self.bot_username
+            .is_none_or(|username| username.eq_ignore_ascii_case(bot_username))
+    }
+
+    /// Whether this is the command with the given name, ignoring the case.
+    pub fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            Command::parse("/start"),
+            Some(Command {
+                name: "start",
+                bot_username: None,
+                args: ""
+            })
+        );
+        assert_eq!(
+            Command::parse("/Roll@Dice_Bot\n2d6"),
+            Some(Command {
+                name: "Roll",
+                bot_username: Some("Dice_Bot"),
+                args: "2d6"
+            })
+        );
+        assert!(Command::parse("/").is_none());
+        assert!(Command::parse("/start@").is_none());
+        assert!(Command::parse("/not-a-command").is_none());
+        assert!(Command::parse(&format!("/{}", "a".repeat(33))).is_none());
+
+        let command = Command::parse("/roll@dice_bot").unwrap();
+        assert!(command.is("ROLL"));
+        assert!(command.is_for("Dice_Bot"));
+        assert!(!command.is_for("other_bot"));
+    }
+}
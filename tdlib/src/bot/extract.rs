@@ -0,0 +1,105 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::Context;
+use crate::enums::{self, MessageReplyTo, MessageSender};
+use crate::types::Message;
+use std::future::Future;
+
+/// A value taken from the message being handled, as an argument of a
+/// handler. A handler is only called if all its arguments can be taken from
+/// the message, while an `Option` of an extractor is always available.
+pub trait FromContext: Sized + Send {
+    /// Take the value from the context, or `None` if the message doesn't
+    /// have it.
+    fn from_context(context: &Context) -> impl Future<Output = Option<Self>> + Send;
+}
+
+impl FromContext for Context {
+    async fn from_context(context: &Context) -> Option<Self> {
+        Some(context.clone())
+    }
+}
+
+impl FromContext for Message {
+    async fn from_context(context: &Context) -> Option<Self> {
+        Some(context.message().clone())
+    }
+}
+
+impl<T: FromContext> FromContext for Option<T> {
+    async fn from_context(context: &Context) -> Option<Self> {
+        Some(T::from_context(context).await)
+    }
+}
+
+/// The identifier of the chat of the message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChatId(pub i64);
+
+impl FromContext for ChatId {
+    async fn from_context(context: &Context) -> Option<Self> {
+        Some(ChatId(context.message().chat_id))
+    }
+}
+
+/// The sender of the message, a user or a chat.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sender(pub MessageSender);
+
+impl FromContext for Sender {
+    async fn from_context(context: &Context) -> Option<Self> {
+        Some(Sender(context.message().sender_id.clone()))
+    }
+}
+
+/// The text of a text message, without its entities.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Text(pub String);
+
+impl FromContext for Text {
+    async fn from_context(context: &Context) -> Option<Self> {
+        context.text().map(|text| Text(text.into()))
+    }
+}
+
+/// The arguments of a command, following its name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Args(pub String);
+
+impl FromContext for Args {
+    async fn from_context(context: &Context) -> Option<Self> {
+        context.command().map(|command| Args(command.args.into()))
+    }
+}
+
+/// The message the message replies to, fetched with `getRepliedMessage`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reply(pub Message);
+
+impl FromContext for Reply {
+    async fn from_context(context: &Context) -> Option<Self> {
+        let message = context.message();
+        if !matches!(message.reply_to, Some(MessageReplyTo::Message(_))) {
+            return None;
+        }
+
+        let replied = context
+            .client()
+            .get_replied_message(message.chat_id, message.id)
+            .await;
+        match replied {
+            Ok(enums::Message::Message(message)) => Some(Reply(message)),
+            #[cfg(feature = "unknown-variants")]
+            Ok(enums::Message::Unrecognized(_)) => None,
+            Err(e) => {
+                log::warn!("Failed to get the replied message: {}", e);
+                None
+            }
+        }
+    }
+}
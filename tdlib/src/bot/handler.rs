@@ -0,0 +1,75 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Context, FromContext};
+use crate::Error;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+pub(crate) type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// An async function handling messages, whose arguments are extractors
+/// taking their value from the message. It's implemented for the functions
+/// with up to 6 arguments implementing `FromContext`, returning a future of
+/// a `Result<(), Error>`.
+pub trait Handler<Args>: Send + Sync + 'static {
+    /// Call the handler, returning `None` without calling it if one of its
+    /// arguments isn't available.
+    fn call(&self, context: Context) -> impl Future<Output = Option<Result<(), Error>>> + Send;
+}
+
+macro_rules! impl_handler {
+    ($($arg:ident),*) => {
+        impl<F, Fut, $($arg,)*> Handler<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Result<(), Error>> + Send,
+            $($arg: FromContext,)*
+        {
+            #[allow(non_snake_case, unused_variables)]
+            async fn call(&self, context: Context) -> Option<Result<(), Error>> {
+                $(let $arg = $arg::from_context(&context).await?;)*
+                Some(self($($arg),*).await)
+            }
+        }
+    };
+}
+
+impl_handler!();
+impl_handler!(A);
+impl_handler!(A, B);
+impl_handler!(A, B, C);
+impl_handler!(A, B, C, D);
+impl_handler!(A, B, C, D, E);
+impl_handler!(A, B, C, D, E, G);
+
+/// A `Handler` whose arguments are hidden, so that handlers with different
+/// arguments can be stored together.
+pub(crate) trait ErasedHandler: Send + Sync {
+    fn call(&self, context: Context) -> BoxFuture<'_, Option<Result<(), Error>>>;
+}
+
+pub(crate) struct Erased<H, Args> {
+    handler: H,
+    args: PhantomData<fn() -> Args>,
+}
+
+impl<H, Args> Erased<H, Args> {
+    pub fn new(handler: H) -> Self {
+        Erased {
+            handler,
+            args: PhantomData,
+        }
+    }
+}
+
+impl<H: Handler<Args>, Args: 'static> ErasedHandler for Erased<H, Args> {
+    fn call(&self, context: Context) -> BoxFuture<'_, Option<Result<(), Error>>> {
+        Box::pin(self.handler.call(context))
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::input_message_text;
+use crate::enums::{ChatType, InlineQueryResultsButtonType, InputInlineQueryResult};
+use crate::types::{
+    FormattedText, InlineQueryResultsButton, InputInlineQueryResultArticle, Location,
+    UpdateNewInlineQuery,
+};
+use crate::{Client, Error};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The maximum number of results of an answer to an inline query, from the
+/// Bot API.
+const MAX_RESULTS: usize = 50;
+
+/// The results of an inline query, for `InlineQuery::answer`.
+///
+/// # Examples
+///
+/// Showing the results 10 at a time, as the user scrolls them:
+///
+/// ```no_run
+/// use tdlib::bot::{InlineQuery, InlineResults};
+/// use tdlib::Error;
+///
+/// async fn search(query: InlineQuery) -> Result<(), Error> {
+///     let words = ["alpha", "beta", "gamma", "delta"];
+///     let results = words
+///         .iter()
+///         .filter(|word| word.contains(query.query()))
+///         .map(|word| InlineResults::article(*word, *word, *word));
+///     query
+///         .answer(InlineResults::paginate(results, query.offset(), 10))
+///         .await
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InlineResults {
+    results: Vec<InputInlineQueryResult>,
+    next_offset: String,
+    cache_time: i32,
+    is_personal: bool,
+    button: Option<InlineQueryResultsButton>,
+}
+
+impl InlineResults {
+    /// Create an answer without results.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an answer with a page of results. The offset is the one of the
+    /// query, which is the number of results shown in the previous pages, and
+    /// the offset of the next page is set if there are more results. The
+    /// pages have at most 50 results.
+    pub fn paginate(
+        results: impl IntoIterator<Item = InputInlineQueryResult>,
+        offset: &str,
+        page_size: usize,
+    ) -> Self {
+        let start = offset.parse::<usize>().unwrap_or(0);
+        let page_size = page_size.clamp(1, MAX_RESULTS);
+
+        let mut results = results.into_iter().skip(start);
+        let page = results.by_ref().take(page_size).collect();
+        let next_offset = match results.next() {
+            Some(_) => (start + page_size).to_string(),
+            None => String::new(),
+        };
+        InlineResults {
+            results: page,
+            next_offset,
+            ..Default::default()
+        }
+    }
+
+    /// A result sending a text message, with a title and a unique
+    /// identifier.
+    pub fn article(
+        id: impl Into<String>,
+        title: impl Into<String>,
+        text: impl Into<FormattedText>,
+    ) -> InputInlineQueryResult {
+        InputInlineQueryResult::Article(InputInlineQueryResultArticle {
+            id: id.into(),
+            url: String::new(),
+            hide_url: false,
+            title: title.into(),
+            description: String::new(),
+            thumbnail_url: String::new(),
+            thumbnail_width: 0,
+            thumbnail_height: 0,
+            reply_markup: None,
+            input_message_content: input_message_text(text),
+        })
+    }
+
+    /// Add a result.
+    pub fn result(mut self, result: InputInlineQueryResult) -> Self {
+        self.results.push(result);
+        self
+    }
+
+    /// Set the offset of the query asking for the next results. There are no
+    /// more results if it's empty.
+    pub fn next_offset(mut self, offset: impl Into<String>) -> Self {
+        self.next_offset = offset.into();
+        self
+    }
+
+    /// Set the time in seconds the results can be cached by the server.
+    pub fn cache_time(mut self, seconds: i32) -> Self {
+        self.cache_time = seconds;
+        self
+    }
+
+    /// Only cache the results for the user who sent the query.
+    pub fn personal(mut self) -> Self {
+        self.is_personal = true;
+        self
+    }
+
+    /// Show a button above the results.
+    pub fn button(mut self, text: impl Into<String>, r#type: InlineQueryResultsButtonType) -> Self {
+        self.button = Some(InlineQueryResultsButton {
+            text: text.into(),
+            r#type,
+        });
+        self
+    }
+}
+
+/// The inline query being handled, with the client it was received by.
+#[derive(Clone)]
+pub struct InlineQuery {
+    client: Arc<Client>,
+    update: Arc<UpdateNewInlineQuery>,
+    answered: Arc<AtomicBool>,
+}
+
+impl InlineQuery {
+    pub(crate) fn new(client: Arc<Client>, update: &UpdateNewInlineQuery) -> Self {
+        InlineQuery {
+            client,
+            update: Arc::new(update.clone()),
+            answered: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The client of the bot.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The identifier of the query.
+    pub fn id(&self) -> i64 {
+        self.update.id
+    }
+
+    /// The identifier of the user who sent the query.
+    pub fn sender_user_id(&self) -> i64 {
+        self.update.sender_user_id
+    }
+
+    /// The text of the query.
+    pub fn query(&self) -> &str {
+        &self.update.query
+    }
+
+    /// The offset of the results to return, empty for the first page.
+    pub fn offset(&self) -> &str {
+        &self.update.offset
+    }
+
+    /// The location of the user, if the bot asks for it.
+    pub fn user_location(&self) -> Option<&Location> {
+        self.update.user_location.as_ref()
+    }
+
+    /// The type of the chat the query was sent from, if known.
+    pub fn chat_type(&self) -> Option<&ChatType> {
+        self.update.chat_type.as_ref()
+    }
+
+    /// Whether the query was already answered.
+    pub fn is_answered(&self) -> bool {
+        self.answered.load(Ordering::SeqCst)
+    }
+
+    /// Answer the query with `answerInlineQuery`. A query can only be
+    /// answered once, so nothing is sent if it was already answered. The
+    /// query can be answered again if answering it failed.
+    pub async fn answer(&self, results: InlineResults) -> Result<(), Error> {
+        if self.answered.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let result = self
+            .client
+            .answer_inline_query(
+                self.update.id,
+                results.is_personal,
+                results.button,
+                results.results,
+                results.cache_time,
+                results.next_offset,
+            )
+            .await;
+        if result.is_err() {
+            self.answered.store(false, Ordering::SeqCst);
+        }
+        result
+    }
+}
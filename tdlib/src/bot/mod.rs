@@ -0,0 +1,846 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A small framework for bots, routing the new messages to async handlers by
+//! their command.
+//!
+//! The handlers take extractors as arguments, like `ChatId` or `Args`, and
+//! are only called for the messages having all of them. The first handler
+//! of a command which can be called handles the message, and the messages
+//! not handled by a command go to the handlers registered with `message`.
+//!
+//! The callback queries go to the first handler registered with
+//! `callback_query` whose type the data of the button decodes to, and are
+//! answered automatically if the handler doesn't answer them in time. The
+//! inline queries go to the handler registered with `inline_query`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use tdlib::bot::{Args, Bot, Context};
+//! use tdlib::{Client, Error};
+//!
+//! async fn echo(context: Context, Args(args): Args) -> Result<(), Error> {
+//!     context.reply(args.as_str()).await?;
+//!     Ok(())
+//! }
+//!
+//! # async fn example(client: Client) {
+//! let bot = Bot::new(client)
+//!     .username("echo_bot")
+//!     .command("echo", echo)
+//!     .command("start", |context: Context| async move {
+//!         context.send("Send /echo followed by some text").await?;
+//!         Ok(())
+//!     });
+//! bot.run().await;
+//! # }
+//! ```
+mod callback;
+mod command;
+mod extract;
+mod handler;
+mod inline;
+
+pub use callback::{
+    callback_button, CallbackAnswer, CallbackData, CallbackOrigin, CallbackQuery, DataTooLong, Json,
+};
+pub use command::Command;
+pub use extract::{Args, ChatId, FromContext, Reply, Sender, Text};
+pub use handler::Handler;
+pub use inline::{InlineQuery, InlineResults};
+
+use crate::enums::{self, InputMessageContent, MessageContent, MessageReplyTo, Update};
+use crate::timer::Delay;
+use crate::types::{
+    FormattedText, InputMessageText, Message, MessageReplyToMessage, UpdateNewCallbackQuery,
+    UpdateNewInlineCallbackQuery, UpdateNewInlineQuery, UpdateNewMessage,
+};
+use crate::{dispatch_update, Client, Error, UpdateHandler};
+use callback::{ErasedCallbackHandler, Typed};
+use futures_util::future::{self, Either};
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt;
+use handler::{BoxFuture, Erased, ErasedHandler};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long the handlers of callback queries have to answer them before
+/// they're answered automatically, by default.
+const CALLBACK_ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hooks called around the handling of every message by a `Bot`, in the
+/// order they were added before the handlers, and in the reverse order after
+/// them.
+pub trait Middleware: Send + Sync + 'static {
+    /// Called before the handlers. Returning `false` stops the handling of
+    /// the message, and the following middlewares aren't called.
+    fn before(&self, context: &Context) -> impl Future<Output = bool> + Send {
+        let _ = context;
+        async { true }
+    }
+
+    /// Called after the handlers, with their result.
+    fn after(
+        &self,
+        context: &Context,
+        result: &Result<(), Error>,
+    ) -> impl Future<Output = ()> + Send {
+        let _ = (context, result);
+        async {}
+    }
+}
+
+trait ErasedMiddleware: Send + Sync {
+    fn before<'a>(&'a self, context: &'a Context) -> BoxFuture<'a, bool>;
+
+    fn after<'a>(
+        &'a self,
+        context: &'a Context,
+        result: &'a Result<(), Error>,
+    ) -> BoxFuture<'a, ()>;
+}
+
+impl<M: Middleware> ErasedMiddleware for M {
+    fn before<'a>(&'a self, context: &'a Context) -> BoxFuture<'a, bool> {
+        Box::pin(Middleware::before(self, context))
+    }
+
+    fn after<'a>(
+        &'a self,
+        context: &'a Context,
+        result: &'a Result<(), Error>,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(Middleware::after(self, context, result))
+    }
+}
+
+/// The content of a text message, for `sendMessage` and the other
+/// functions sending messages.
+pub fn input_message_text(text: impl Into<FormattedText>) -> InputMessageContent {
+    InputMessageContent::InputMessageText(InputMessageText {
+        text: text.into(),
+        disable_web_page_preview: false,
+        clear_draft: false,
+    })
+}
+
+/// The message being handled, with the client it was received by.
+#[derive(Clone)]
+pub struct Context {
+    client: Arc<Client>,
+    message: Arc<Message>,
+}
+
+impl Context {
+    /// The client of the bot.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The message being handled.
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    /// The text of the message, if it's a text message.
+    pub fn text(&self) -> Option<&str> {
+        match &self.message.content {
+            MessageContent::MessageText(content) => Some(&content.text.text),
+            _ => None,
+        }
+    }
+
+    /// The command the message starts with, if any.
+    pub fn command(&self) -> Option<Command<'_>> {
+        self.text().and_then(Command::parse)
+    }
+
+    /// Send a text message to the chat of the message, in the same thread.
+    pub async fn send(&self, text: impl Into<FormattedText>) -> Result<Message, Error> {
+        self.send_message(text.into(), None).await
+    }
+
+    /// Send a text message replying to the message.
+    pub async fn reply(&self, text: impl Into<FormattedText>) -> Result<Message, Error> {
+        let reply_to = MessageReplyTo::Message(MessageReplyToMessage {
+            chat_id: self.message.chat_id,
+            message_id: self.message.id,
+        });
+        self.send_message(text.into(), Some(reply_to)).await
+    }
+
+    async fn send_message(
+        &self,
+        text: FormattedText,
+        reply_to: Option<MessageReplyTo>,
+    ) -> Result<Message, Error> {
+        let message = self
+            .client
+            .send_message(
+                self.message.chat_id,
+                self.message.message_thread_id,
+                reply_to,
+                None,
+                None,
+                input_message_text(text),
+            )
+            .await?;
+        match message {
+            enums::Message::Message(message) => Ok(message),
+            #[cfg(feature = "unknown-variants")]
+            enums::Message::Unrecognized(json) => Err(Error::unrecognized(&json)),
+        }
+    }
+}
+
+type InlineHandler =
+    Box<dyn Fn(InlineQuery) -> BoxFuture<'static, Result<(), Error>> + Send + Sync>;
+
+/// Routes the new messages and queries received by a client to handlers.
+///
+/// The bot can be run with `run`, which handles the updates concurrently, or
+/// fed the updates through `dispatch_update`, since it's an `UpdateHandler`,
+/// in which case they're handled one at a time.
+pub struct Bot {
+    client: Arc<Client>,
+    username: Option<String>,
+    commands: Vec<(String, Box<dyn ErasedHandler>)>,
+    messages: Vec<Box<dyn ErasedHandler>>,
+    middlewares: Vec<Box<dyn ErasedMiddleware>>,
+    callback_queries: Vec<Box<dyn ErasedCallbackHandler>>,
+    games: Vec<Box<dyn ErasedCallbackHandler>>,
+    callback_answer_timeout: Duration,
+    inline_query: Option<InlineHandler>,
+}
+
+impl Bot {
+    /// Create a bot without handlers, handling the messages of a client.
+    pub fn new(client: impl Into<Arc<Client>>) -> Self {
+        Bot {
+            client: client.into(),
+            username: None,
+            commands: Vec::new(),
+            messages: Vec::new(),
+            middlewares: Vec::new(),
+            callback_queries: Vec::new(),
+            games: Vec::new(),
+            callback_answer_timeout: CALLBACK_ANSWER_TIMEOUT,
+            inline_query: None,
+        }
+    }
+
+    /// The client of the bot.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Set the username of the bot, so that the commands addressed to other
+    /// bots, like `/start@other_bot`, are ignored. All the commands are
+    /// handled otherwise.
+    pub fn username(mut self, username: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    /// Add a handler of a command, by its name without the leading `/`.
+    pub fn command<H: Handler<Args>, Args: 'static>(
+        mut self,
+        name: impl Into<String>,
+        handler: H,
+    ) -> Self {
+        self.commands
+            .push((name.into(), Box::new(Erased::new(handler))));
+        self
+    }
+
+    /// Add a handler of the messages not handled by a command.
+    pub fn message<H: Handler<Args>, Args: 'static>(mut self, handler: H) -> Self {
+        self.messages.push(Box::new(Erased::new(handler)));
+        self
+    }
+
+    /// Add hooks called around the handling of every message.
+    pub fn middleware(mut self, middleware: impl Middleware) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    /// Add a handler of the callback queries whose data decodes to `T`. The
+    /// handlers are tried in the order they were added, and the first one
+    /// taking the data handles the query.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use serde::{Deserialize, Serialize};
+    /// use tdlib::bot::{Bot, CallbackAnswer, CallbackQuery, Json};
+    /// use tdlib::{Client, Error};
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct Vote {
+    ///     poll: i64,
+    ///     up: bool,
+    /// }
+    ///
+    /// async fn vote(query: CallbackQuery, Json(vote): Json<Vote>) -> Result<(), Error> {
+    ///     let text = if vote.up { "Voted up" } else { "Voted down" };
+    ///     query.answer(CallbackAnswer::notification(text)).await
+    /// }
+    ///
+    /// # fn example(client: Client) {
+    /// let bot = Bot::new(client).callback_query(vote);
+    /// # }
+    /// ```
+    pub fn callback_query<T, F, Fut>(mut self, handler: F) -> Self
+    where
+        T: CallbackData,
+        F: Fn(CallbackQuery, T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.callback_queries.push(Box::new(Typed::new(handler)));
+        self
+    }
+
+    /// Add a handler of the callback queries of the game buttons, taking the
+    /// short name of the game. The query should be answered with the URL of
+    /// the game, with `CallbackAnswer::url`.
+    pub fn game_query<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(CallbackQuery, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.games.push(Box::new(Typed::new(handler)));
+        self
+    }
+
+    /// Set how long the handlers of the callback queries have to answer
+    /// them, 10 seconds by default. The queries not answered in time are
+    /// answered without showing anything while their handler keeps running,
+    /// so that the app of the user stops waiting, and the later answers of
+    /// the handler are ignored.
+    pub fn callback_answer_timeout(mut self, timeout: Duration) -> Self {
+        self.callback_answer_timeout = timeout;
+        self
+    }
+
+    /// Set the handler of the inline queries, which should answer them with
+    /// `InlineQuery::answer`.
+    pub fn inline_query<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(InlineQuery) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.inline_query = Some(Box::new(move |query| Box::pin(handler(query))));
+        self
+    }
+
+    /// Handle a new message, unless it was sent by the bot itself, returning
+    /// the result of its handler.
+    pub async fn handle_message(&self, message: Message) -> Result<(), Error> {
+        if message.is_outgoing {
+            return Ok(());
+        }
+
+        let context = Context {
+            client: self.client.clone(),
+            message: Arc::new(message),
+        };
+        for middleware in &self.middlewares {
+            if !middleware.before(&context).await {
+                return Ok(());
+            }
+        }
+
+        let result = self.route(&context).await;
+        for middleware in self.middlewares.iter().rev() {
+            middleware.after(&context, &result).await;
+        }
+        result
+    }
+
+    async fn route(&self, context: &Context) -> Result<(), Error> {
+        if let Some(command) = context.command() {
+            if let Some(username) = &self.username {
+                if !command.is_for(username) {
+                    return Ok(());
+                }
+            }
+            for (name, handler) in &self.commands {
+                if command.is(name) {
+                    if let Some(result) = handler.call(context.clone()).await {
+                        return result;
+                    }
+                }
+            }
+        }
+
+        for handler in &self.messages {
+            if let Some(result) = handler.call(context.clone()).await {
+                return result;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a callback query, returning the result of its handler. The
+    /// query is answered without showing anything if the handler doesn't
+    /// answer it in time, or if there's no handler for it.
+    pub async fn handle_callback_query(&self, query: CallbackQuery) -> Result<(), Error> {
+        let handling = pin!(self.route_callback_query(&query));
+        let result = match future::select(handling, Delay::new(self.callback_answer_timeout)).await
+        {
+            Either::Left((result, _)) => result,
+            Either::Right((_, handling)) => {
+                if let Err(e) = query.answer(CallbackAnswer::default()).await {
+                    log::warn!("Failed to answer a callback query: {}", e);
+                }
+                handling.await
+            }
+        };
+
+        let answered = query.answer(CallbackAnswer::default()).await;
+        result.and(answered)
+    }
+
+    async fn route_callback_query(&self, query: &CallbackQuery) -> Result<(), Error> {
+        let (handlers, data) = match (query.game_short_name(), query.data()) {
+            (Some(short_name), _) => (&self.games, short_name.as_bytes().to_vec()),
+            (None, Some(data)) => (&self.callback_queries, data),
+            (None, None) => return Ok(()),
+        };
+
+        for handler in handlers {
+            if let Some(handling) = handler.call(query.clone(), &data) {
+                return handling.await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle an inline query, returning the result of its handler. The
+    /// query isn't answered if there's no handler.
+    pub async fn handle_inline_query(&self, query: InlineQuery) -> Result<(), Error> {
+        match &self.inline_query {
+            Some(handler) => handler(query).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Handle the updates of the client until it's closed. The updates are
+    /// handled concurrently, so that a slow handler doesn't delay the others,
+    /// nor the automatic answers to the callback queries. Returns once the
+    /// handlers still running when the client is closed are done.
+    pub async fn run(&self) {
+        let mut updates = self.client.updates();
+        let mut handling = FuturesUnordered::new();
+        loop {
+            let update = if handling.is_empty() {
+                updates.next().await
+            } else {
+                match future::select(updates.next(), handling.next()).await {
+                    Either::Left((update, _)) => update,
+                    Either::Right(_) => continue,
+                }
+            };
+            match update {
+                Some(update) => handling.push(async move { dispatch_update(self, &update).await }),
+                None => break,
+            }
+        }
+        while handling.next().await.is_some() {}
+    }
+
+    /// Handle an update, ignoring the updates which aren't new messages or
+    /// queries.
+    pub async fn handle_update(&self, update: &Update) {
+        dispatch_update(self, update).await;
+    }
+}
+
+impl UpdateHandler for Bot {
+    async fn on_new_message(&self, update: &UpdateNewMessage) {
+        if let Err(e) = self.handle_message(update.message.clone()).await {
+            log::warn!("Failed to handle a message: {}", e);
+        }
+    }
+
+    async fn on_new_callback_query(&self, update: &UpdateNewCallbackQuery) {
+        let query = CallbackQuery::new(self.client.clone(), update);
+        if let Err(e) = self.handle_callback_query(query).await {
+            log::warn!("Failed to handle a callback query: {}", e);
+        }
+    }
+
+    async fn on_new_inline_callback_query(&self, update: &UpdateNewInlineCallbackQuery) {
+        let query = CallbackQuery::new_inline(self.client.clone(), update);
+        if let Err(e) = self.handle_callback_query(query).await {
+            log::warn!("Failed to handle a callback query: {}", e);
+        }
+    }
+
+    async fn on_new_inline_query(&self, update: &UpdateNewInlineQuery) {
+        let query = InlineQuery::new(self.client.clone(), update);
+        if let Err(e) = self.handle_inline_query(query).await {
+            log::warn!("Failed to handle an inline query: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::CallbackQueryPayload;
+    use crate::mock::MockBackend;
+    use crate::test_util;
+    use crate::types::{CallbackQueryPayloadData, MessageText, UpdateAuthorizationState};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    fn message(id: i64, text: &str) -> Message {
+        let mut message = test_util::message(id);
+        message.content = MessageContent::MessageText(MessageText {
+            text: text.into(),
+            web_page: None,
+        });
+        message
+    }
+
+    struct Counter(Arc<AtomicUsize>);
+
+    impl Middleware for Counter {
+        async fn before(&self, context: &Context) -> bool {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            context.text() != Some("blocked")
+        }
+    }
+
+    #[tokio::test]
+    async fn route_by_command() {
+        let mock = Arc::new(MockBackend::new());
+        let client = Client::builder().backend(mock.clone()).build();
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let handled_commands = handled.clone();
+        let handled_messages = handled.clone();
+        let bot = Bot::new(client)
+            .username("test_bot")
+            .middleware(Counter(count.clone()))
+            .command("roll", move |ChatId(chat_id): ChatId, Args(args): Args| {
+                let handled = handled_commands.clone();
+                async move {
+                    handled
+                        .lock()
+                        .unwrap()
+                        .push(format!("roll {} in {}", args, chat_id));
+                    Ok(())
+                }
+            })
+            .message(move |Text(text): Text| {
+                let handled = handled_messages.clone();
+                async move {
+                    handled.lock().unwrap().push(format!("text {}", text));
+                    Ok(())
+                }
+            });
+
+        for (id, text) in [
+            (1, "/roll@test_bot 2d6"),
+            (2, "/ROLL"),
+            (3, "/roll@other_bot 1d4"),
+            (4, "/unknown"),
+            (5, "hello"),
+            (6, "blocked"),
+        ] {
+            bot.handle_message(message(id, text)).await.unwrap();
+        }
+
+        assert_eq!(
+            *handled.lock().unwrap(),
+            vec!["roll 2d6 in 7", "roll  in 7", "text /unknown", "text hello"]
+        );
+        assert_eq!(count.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn reply_with_text() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond_with("sendMessage", |request: &Value| {
+            let text = request["input_message_content"]["text"]["text"]
+                .as_str()
+                .unwrap();
+            serde_json::to_value(enums::Message::Message(message(100, text))).unwrap()
+        });
+        let client = Client::builder().backend(mock.clone()).build();
+        let bot = Bot::new(client).command("start", |context: Context| async move {
+            context.reply("Welcome!").await?;
+            Ok(())
+        });
+
+        bot.handle_update(&Update::NewMessage(UpdateNewMessage {
+            message: message(42, "/start"),
+        }))
+        .await;
+
+        let requests = mock.requests_of_type("sendMessage");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0]["reply_to"],
+            json!({"@type": "messageReplyToMessage", "chat_id": 7, "message_id": 42})
+        );
+        assert_eq!(
+            requests[0]["input_message_content"],
+            json!({
+                "@type": "inputMessageText",
+                "text": {"text": "Welcome!", "entities": []},
+                "disable_web_page_preview": false,
+                "clear_draft": false
+            })
+        );
+    }
+
+    #[cfg(feature = "unknown-variants")]
+    #[tokio::test]
+    async fn reply_of_unknown_type() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond("sendMessage", json!({"@type": "messageFromTheFuture"}));
+        let client = Client::builder().backend(mock.clone()).build();
+        let result = Arc::new(Mutex::new(None));
+        let handler_result = result.clone();
+        let bot = Bot::new(client).command("start", move |context: Context| {
+            let result = handler_result.clone();
+            async move {
+                *result.lock().unwrap() = Some(context.reply("Welcome!").await);
+                Ok(())
+            }
+        });
+
+        bot.handle_update(&Update::NewMessage(UpdateNewMessage {
+            message: message(42, "/start"),
+        }))
+        .await;
+
+        let result = result.lock().unwrap().take().unwrap();
+        assert!(matches!(
+            result,
+            Err(Error::Deserialize { json, .. }) if json.contains(r#""@type":"messageFromTheFuture""#)
+        ));
+    }
+
+    fn callback_query(id: i64, data: &[u8]) -> Update {
+        Update::NewCallbackQuery(UpdateNewCallbackQuery {
+            id,
+            sender_user_id: 1,
+            chat_id: 7,
+            message_id: 42,
+            chat_instance: 0,
+            payload: CallbackQueryPayload::Data(CallbackQueryPayloadData {
+                data: STANDARD.encode(data),
+            }),
+        })
+    }
+
+    #[tokio::test]
+    async fn answer_callback_queries() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond("answerCallbackQuery", json!({"@type": "ok"}));
+        let client = Client::builder().backend(mock.clone()).build();
+        let bot = Bot::new(client)
+            .callback_query(
+                |query: CallbackQuery, Json(votes): Json<(String, i32)>| async move {
+                    let text = format!("{} has {} votes", votes.0, votes.1);
+                    query.answer(CallbackAnswer::alert(text)).await?;
+                    // Ignored, since the query was already answered
+                    query.answer("again").await
+                },
+            )
+            .callback_query(|query: CallbackQuery, data: String| async move {
+                assert_eq!(
+                    query.origin(),
+                    &CallbackOrigin::Message {
+                        chat_id: 7,
+                        message_id: 42
+                    }
+                );
+                assert_eq!(data, "not json");
+                Ok(())
+            });
+
+        let data = Json(("rust".to_string(), 3)).encode();
+        bot.handle_update(&callback_query(1, &data)).await;
+        bot.handle_update(&callback_query(2, b"not json")).await;
+        // Not UTF-8, so taken by none of the handlers
+        bot.handle_update(&callback_query(3, &[0xff])).await;
+
+        let answers = mock
+            .requests_of_type("answerCallbackQuery")
+            .into_iter()
+            .map(|request| {
+                (
+                    request["callback_query_id"].clone(),
+                    request["text"].clone(),
+                    request["show_alert"].clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            answers,
+            vec![
+                (json!("1"), json!("rust has 3 votes"), json!(true)),
+                (json!("2"), json!(""), json!(false)),
+                (json!("3"), json!(""), json!(false)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_answers_can_be_retried() {
+        let mock = Arc::new(MockBackend::new());
+        let client = Arc::new(Client::builder().backend(mock.clone()).build());
+        let update = match callback_query(1, b"data") {
+            Update::NewCallbackQuery(update) => update,
+            _ => unreachable!(),
+        };
+        let query = CallbackQuery::new(client, &update);
+
+        assert!(query.answer("first").await.is_err());
+        assert!(!query.is_answered());
+        mock.respond("answerCallbackQuery", json!({"@type": "ok"}));
+        query.answer("second").await.unwrap();
+        assert!(query.is_answered());
+        assert_eq!(mock.requests_of_type("answerCallbackQuery").len(), 2);
+    }
+
+    #[test]
+    fn callback_data_length() {
+        assert!(callback_button("ok", &vec![0; 64]).is_ok());
+        assert_eq!(
+            callback_button("long", &"x".repeat(65)),
+            Err(DataTooLong { length: 65 })
+        );
+    }
+
+    #[tokio::test]
+    async fn answer_slow_callback_queries_in_time() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond("answerCallbackQuery", json!({"@type": "ok"}));
+        let client = Client::builder().backend(mock.clone()).build();
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        let handler_finished = finished.clone();
+        let bot = Bot::new(client)
+            .callback_answer_timeout(Duration::from_millis(20))
+            .callback_query(move |query: CallbackQuery, _: Vec<u8>| {
+                let finished = handler_finished.clone();
+                async move {
+                    Delay::new(Duration::from_millis(200)).await;
+                    assert!(query.is_answered());
+                    query.answer("too late").await?;
+                    finished.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            });
+
+        bot.handle_update(&callback_query(1, b"slow")).await;
+
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+        let answers = mock.requests_of_type("answerCallbackQuery");
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0]["text"], "");
+    }
+
+    #[tokio::test]
+    async fn run_handles_slow_callback_queries_concurrently() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond("answerCallbackQuery", json!({"@type": "ok"}));
+        let client = Client::builder().backend(mock.clone()).build();
+        let client_id = client.id();
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        let handler_finished = finished.clone();
+        let bot = Bot::new(client)
+            .callback_answer_timeout(Duration::from_millis(20))
+            .callback_query(move |_: CallbackQuery, _: Vec<u8>| {
+                let finished = handler_finished.clone();
+                async move {
+                    Delay::new(Duration::from_millis(300)).await;
+                    finished.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            });
+
+        future::join(bot.run(), async {
+            mock.push_update(client_id, callback_query(1, b"slow"));
+            mock.push_update(client_id, callback_query(2, b"slow"));
+            Delay::new(Duration::from_millis(150)).await;
+
+            // Both answered in time, while the first handler is still running
+            assert_eq!(mock.requests_of_type("answerCallbackQuery").len(), 2);
+            assert_eq!(finished.load(Ordering::SeqCst), 0);
+            mock.push_update(
+                client_id,
+                Update::AuthorizationState(UpdateAuthorizationState {
+                    authorization_state: enums::AuthorizationState::Closed,
+                }),
+            );
+        })
+        .await;
+
+        assert_eq!(finished.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn paginate_inline_results() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond("answerInlineQuery", json!({"@type": "ok"}));
+        let client = Client::builder().backend(mock.clone()).build();
+        let bot = Bot::new(client).inline_query(|query: InlineQuery| async move {
+            let results = (0..25)
+                .map(|i| format!("{} {}", query.query(), i))
+                .map(|text| InlineResults::article(text.clone(), text.clone(), text));
+            let results = InlineResults::paginate(results, query.offset(), 10).cache_time(60);
+            query.answer(results).await
+        });
+
+        for (id, offset) in [(1, ""), (2, "20")] {
+            bot.handle_update(&Update::NewInlineQuery(UpdateNewInlineQuery {
+                id,
+                sender_user_id: 1,
+                user_location: None,
+                chat_type: None,
+                query: "item".into(),
+                offset: offset.into(),
+            }))
+            .await;
+        }
+
+        let answers = mock.requests_of_type("answerInlineQuery");
+        assert_eq!(answers.len(), 2);
+        let results = answers[0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 10);
+        assert_eq!(results[0]["@type"], "inputInlineQueryResultArticle");
+        assert_eq!(results[0]["title"], "item 0");
+        assert_eq!(
+            results[0]["input_message_content"]["text"]["text"],
+            "item 0"
+        );
+        assert_eq!(answers[0]["next_offset"], "10");
+        assert_eq!(answers[0]["cache_time"], 60);
+
+        let results = answers[1]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0]["id"], "item 20");
+        assert_eq!(answers[1]["next_offset"], "");
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An ordered view of a chat list, following the protocol described in the
+//! [TdLib documentation](https://core.telegram.org/tdlib/getting-started#getting-the-lists-of-chats):
+//! chats are loaded with `loadChats` until TdLib answers with a 404 error,
+//! and sorted by the `(order, chat_id)` pairs of their positions, which
+//! arrive through updates.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use std::sync::Arc;
+//! use tdlib::chat_list::ChatListView;
+//! use tdlib::enums::ChatList;
+//! use tdlib::Client;
+//!
+//! # async fn example(client: Client) -> Result<(), tdlib::Error> {
+//! let view = Arc::new(ChatListView::new(ChatList::Main));
+//!
+//! // The updates must be applied to the view while loading the chats
+//! let mut updates = client.updates();
+//! let updates_view = view.clone();
+//! tokio::spawn(async move {
+//!     while let Some(update) = updates.next().await {
+//!         updates_view.apply(&update);
+//!     }
+//! });
+//!
+//! let mut diffs = view.diffs();
+//! view.load_all(&client).await?;
+//! while let Some(diff) = diffs.next().await {
+//!     println!("{:?}", diff);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::enums::{ChatList, Update};
+use crate::types::ChatPosition;
+use crate::updates::{OverflowPolicy, Subscription};
+use crate::{Client, Error, ErrorKind};
+use futures_core::Stream;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+const DEFAULT_DIFFS_BUFFER: usize = 1024;
+
+/// The number of chats asked to TdLib by `ChatListView::load_all` at a time.
+const LOAD_LIMIT: i32 = 100;
+
+/// A change of a chat list, with the indices of the chat in the list as
+/// displayed, from the top.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatListDiff {
+    /// The chat was added to the list at `index`.
+    Inserted { chat_id: i64, index: usize },
+    /// The chat moved from the index `from` to the index `to`, where `from`
+    /// is its index before the move and `to` the one after it.
+    Moved {
+        chat_id: i64,
+        from: usize,
+        to: usize,
+    },
+    /// The chat at `index` was removed from the list.
+    Removed { chat_id: i64, index: usize },
+}
+
+/// The chats of a list, sorted by the `(order, chat_id)` pairs of their
+/// positions. Also used for the chat lists of the `State`.
+#[derive(Default)]
+pub(crate) struct Chats {
+    /// The `(order, chat_id)` pairs, sorted in reverse compared to the list
+    /// as displayed.
+    sorted: BTreeSet<(i64, i64)>,
+    orders: HashMap<i64, i64>,
+}
+
+impl Chats {
+    /// The identifiers of the chats, in the order they should be displayed.
+    pub(crate) fn chat_ids(&self) -> impl Iterator<Item = i64> + '_ {
+        self.sorted.iter().rev().map(|(_, chat_id)| *chat_id)
+    }
+
+    /// The index of an entry in the list as displayed.
+    fn index_of(&self, entry: (i64, i64)) -> usize {
+        self.sorted
+            .range((Bound::Excluded(entry), Bound::Unbounded))
+            .count()
+    }
+
+    /// Move a chat to its new order, removing it from the list if the order
+    /// is 0. Returns the change of the list, if any.
+    pub(crate) fn set_order(&mut self, chat_id: i64, order: i64) -> Option<ChatListDiff> {
+        let old_order = self.orders.get(&chat_id).copied();
+        if old_order == Some(order) || (old_order.is_none() && order == 0) {
+            return None;
+        }
+
+        let from = old_order.map(|old_order| {
+            let index = self.index_of((old_order, chat_id));
+            self.sorted.remove(&(old_order, chat_id));
+            index
+        });
+
+        if order == 0 {
+            self.orders.remove(&chat_id);
+            return from.map(|index| ChatListDiff::Removed { chat_id, index });
+        }
+
+        self.orders.insert(chat_id, order);
+        self.sorted.insert((order, chat_id));
+        let to = self.index_of((order, chat_id));
+        Some(match from {
+            Some(from) => ChatListDiff::Moved { chat_id, from, to },
+            None => ChatListDiff::Inserted { chat_id, index: to },
+        })
+    }
+}
+
+/// The chats of a single chat list (main, archive or a folder), in the order
+/// they should be displayed.
+///
+/// The view must be fed every update of the client with `apply`, and filled
+/// with `load_more` or `load_all`. The current order is available with
+/// `snapshot`, and every following change is notified to the streams
+/// returned by `diffs`.
+pub struct ChatListView {
+    list: ChatList,
+    chats: Mutex<Chats>,
+    loaded: AtomicBool,
+    subscriptions: Mutex<Vec<Arc<Subscription<ChatListDiff>>>>,
+}
+
+impl ChatListView {
+    /// Create an empty view of the given chat list.
+    pub fn new(list: ChatList) -> Self {
+        ChatListView {
+            list,
+            chats: Mutex::default(),
+            loaded: AtomicBool::new(false),
+            subscriptions: Mutex::default(),
+        }
+    }
+
+    /// The chat list of this view.
+    pub fn list(&self) -> &ChatList {
+        &self.list
+    }
+
+    /// Whether TdLib already sent all the chats of the list.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::Acquire)
+    }
+
+    /// Update the view with an update of TdLib, ignoring the updates which
+    /// don't affect the positions of the chats in this list.
+    pub fn apply(&self, update: &Update) {
+        let (chat_id, positions) = match update {
+            Update::NewChat(update) => (update.chat.id, &update.chat.positions[..]),
+            Update::ChatPosition(update) => {
+                (update.chat_id, std::slice::from_ref(&update.position))
+            }
+            Update::ChatLastMessage(update) => (update.chat_id, &update.positions[..]),
+            Update::ChatDraftMessage(update) => (update.chat_id, &update.positions[..]),
+            _ => return,
+        };
+        self.apply_positions(chat_id, positions);
+    }
+
+    fn apply_positions(&self, chat_id: i64, positions: &[ChatPosition]) {
+        let diffs = {
+            let mut chats = self.chats.lock().unwrap();
+            positions
+                .iter()
+                .filter(|position| position.list == self.list)
+                .filter_map(|position| chats.set_order(chat_id, position.order))
+                .collect::<Vec<_>>()
+        };
+        if diffs.is_empty() {
+            return;
+        }
+
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .retain(|subscription| diffs.iter().all(|diff| subscription.push(*diff)));
+    }
+
+    /// Get the identifiers of the chats currently in the list, from the top.
+    pub fn snapshot(&self) -> impl Iterator<Item = i64> {
+        let chats = self.chats.lock().unwrap();
+        chats.chat_ids().collect::<Vec<_>>().into_iter()
+    }
+
+    /// Get a stream of the changes of the list made from now on. Up to 1024
+    /// changes are buffered, the oldest ones being dropped after that, so a
+    /// consumer falling behind should start over from a `snapshot`.
+    pub fn diffs(&self) -> ChatListDiffs {
+        let subscription = Arc::new(Subscription::new(
+            DEFAULT_DIFFS_BUFFER,
+            OverflowPolicy::DropOldest,
+        ));
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .push(subscription.clone());
+        ChatListDiffs { subscription }
+    }
+
+    /// Ask TdLib to send up to `limit` more chats of the list, which arrive
+    /// as updates. Returns `false`, without sending anything, once all the
+    /// chats have been loaded.
+    pub async fn load_more(&self, client: &Client, limit: i32) -> Result<bool, Error> {
+        if self.is_loaded() {
+            return Ok(false);
+        }
+
+        match client.load_chats(Some(self.list.clone()), limit).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                self.loaded.store(true, Ordering::Release);
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Ask TdLib to send all the chats of the list.
+    pub async fn load_all(&self, client: &Client) -> Result<(), Error> {
+        while self.load_more(client, LOAD_LIMIT).await? {}
+        Ok(())
+    }
+}
+
+/// A stream of the changes of a `ChatListView`, returned by
+/// `ChatListView::diffs`.
+pub struct ChatListDiffs {
+    subscription: Arc<Subscription<ChatListDiff>>,
+}
+
+impl Stream for ChatListDiffs {
+    type Item = ChatListDiff;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.subscription.poll_next(cx)
+    }
+}
+
+impl Drop for ChatListDiffs {
+    fn drop(&mut self) {
+        self.subscription.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBackend;
+    use crate::types::{ChatListFolder, UpdateChatLastMessage, UpdateChatPosition};
+    use futures_util::{FutureExt, StreamExt};
+    use serde_json::json;
+    use std::sync::atomic::AtomicU32;
+
+    fn position(list: ChatList, order: i64) -> ChatPosition {
+        ChatPosition {
+            list,
+            order,
+            is_pinned: false,
+            source: None,
+        }
+    }
+
+    fn move_chat(chat_id: i64, order: i64) -> Update {
+        Update::ChatPosition(UpdateChatPosition {
+            chat_id,
+            position: position(ChatList::Main, order),
+        })
+    }
+
+    #[test]
+    fn diffs_follow_positions() {
+        let view = ChatListView::new(ChatList::Main);
+        let mut diffs = view.diffs();
+
+        view.apply(&move_chat(1, 10));
+        view.apply(&move_chat(2, 30));
+        view.apply(&move_chat(3, 20));
+        view.apply(&Update::ChatLastMessage(UpdateChatLastMessage {
+            chat_id: 1,
+            last_message: None,
+            positions: vec![
+                position(ChatList::Main, 40),
+                position(ChatList::Archive, 40),
+            ],
+        }));
+        view.apply(&move_chat(3, 0));
+        view.apply(&move_chat(4, 0));
+        view.apply(&Update::ChatPosition(UpdateChatPosition {
+            chat_id: 5,
+            position: position(ChatList::Folder(ChatListFolder { chat_folder_id: 1 }), 50),
+        }));
+
+        let mut received = Vec::new();
+        while let Some(Some(diff)) = diffs.next().now_or_never() {
+            received.push(diff);
+        }
+        assert_eq!(
+            received,
+            vec![
+                ChatListDiff::Inserted {
+                    chat_id: 1,
+                    index: 0
+                },
+                ChatListDiff::Inserted {
+                    chat_id: 2,
+                    index: 0
+                },
+                ChatListDiff::Inserted {
+                    chat_id: 3,
+                    index: 1
+                },
+                ChatListDiff::Moved {
+                    chat_id: 1,
+                    from: 2,
+                    to: 0
+                },
+                ChatListDiff::Removed {
+                    chat_id: 3,
+                    index: 2
+                },
+            ]
+        );
+        assert_eq!(view.snapshot().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn load_until_not_found() {
+        let mock = Arc::new(MockBackend::new());
+        let count = Arc::new(AtomicU32::new(0));
+        let count_clone = count.clone();
+        mock.respond_with("loadChats", move |_| {
+            if count_clone.fetch_add(1, Ordering::SeqCst) < 2 {
+                json!({"@type": "ok"})
+            } else {
+                json!({"@type": "error", "code": 404, "message": "Not Found"})
+            }
+        });
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let view = ChatListView::new(ChatList::Archive);
+        view.load_all(&client).await.unwrap();
+        assert!(view.is_loaded());
+        assert!(!view.load_more(&client, 10).await.unwrap());
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            mock.requests_of_type("loadChats")[0]["chat_list"],
+            json!({"@type": "chatListArchive"})
+        );
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::enums::{AuthorizationState, Update};
+use crate::pagination::{Paginated, Paginator};
+use crate::runtime::Runtime;
+use crate::updates::{OverflowPolicy, Subscription, Updates};
+use crate::{timeout, Backend, Error, Function, RetryPolicy};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+const DEFAULT_UPDATE_BUFFER: usize = 1024;
+
+/// The state shared between a `Client` and the receiving side.
+pub(crate) struct ClientState {
+    authorization_state: RwLock<Option<AuthorizationState>>,
+    subscriptions: Mutex<Vec<Arc<Subscription>>>,
+    update_buffer: usize,
+    overflow_policy: OverflowPolicy,
+    request_timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl ClientState {
+    /// Update the state of the client and forward the update to its streams.
+    pub fn dispatch(&self, update: &Update) {
+        let closed = match update {
+            Update::AuthorizationState(update) => {
+                *self.authorization_state.write().unwrap() =
+                    Some(update.authorization_state.clone());
+                matches!(update.authorization_state, AuthorizationState::Closed)
+            }
+            _ => false,
+        };
+
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain(|subscription| subscription.push(update.clone()));
+        if closed {
+            subscriptions.drain(..).for_each(|s| s.close());
+        }
+    }
+}
+
+/// A builder to configure a `Client` before creating it.
+#[derive(Clone)]
+pub struct ClientBuilder {
+    update_buffer: usize,
+    overflow_policy: OverflowPolicy,
+    request_timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    backend: Option<Arc<dyn Backend>>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder {
+            update_buffer: DEFAULT_UPDATE_BUFFER,
+            overflow_policy: OverflowPolicy::default(),
+            request_timeout: None,
+            retry_policy: None,
+            backend: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Set the number of updates each `Updates` stream of the client can
+    /// buffer before applying the overflow policy. Defaults to 1024.
+    pub fn update_buffer(mut self, update_buffer: usize) -> Self {
+        self.update_buffer = update_buffer;
+        self
+    }
+
+    /// Set what to do when the buffer of an `Updates` stream is full.
+    /// Defaults to `OverflowPolicy::DropOldest`.
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Set the default time after which the requests sent with the methods
+    /// of the client fail with `Error::Timeout`. By default, requests wait
+    /// for a response indefinitely. Use `timeout` to set a different timeout
+    /// for a single request.
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = Some(request_timeout);
+        self
+    }
+
+    /// Set the policy to automatically retry the idempotent requests failing
+    /// because of rate limits or other transient errors. By default, requests
+    /// are never retried. The request timeout applies to each attempt.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Set the backend used to talk with TdLib. Clients created with the same
+    /// backend instance share the thread receiving from it. By default, the
+    /// real TdLib is used through a backend shared by all the clients.
+    pub fn backend(mut self, backend: Arc<dyn Backend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Create the client and start the background receiver, if it isn't
+    /// running already.
+    pub fn build(self) -> Client {
+        let runtime = match self.backend {
+            Some(backend) => Runtime::for_backend(backend),
+            None => Runtime::default_runtime().clone(),
+        };
+        runtime.start_receiver();
+
+        let id = runtime.backend().create_client();
+        let state = Arc::new(ClientState {
+            authorization_state: RwLock::default(),
+            subscriptions: Mutex::default(),
+            update_buffer: self.update_buffer,
+            overflow_policy: self.overflow_policy,
+            request_timeout: self.request_timeout,
+            retry_policy: self.retry_policy,
+        });
+        runtime.register_client(id, state.clone());
+        Client { id, state, runtime }
+    }
+}
+
+/// A TdLib client. It owns the id returned by TdLib, exposes every TdLib
+/// function as an async method and keeps track of its own authorization
+/// state. When dropped, the client is closed if it wasn't already.
+///
+/// Updates and responses are received by a background thread shared by all
+/// the clients, so `receive` must not be called when using `Client`.
+///
+/// Note that to start receiving updates for a client you need to send at
+/// least a request with it first.
+pub struct Client {
+    id: i32,
+    state: Arc<ClientState>,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    /// Create a new TdLib client with the default configuration.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        ClientBuilder::default().build()
+    }
+
+    /// Create a builder to configure a new TdLib client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// The id of the client as known by TdLib.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// The last authorization state received for this client, if any.
+    pub fn authorization_state(&self) -> Option<AuthorizationState> {
+        self.state.authorization_state.read().unwrap().clone()
+    }
+
+    /// Whether the client has reached `AuthorizationState::Closed`. A
+    /// closed client can't be used to send requests anymore.
+    pub fn is_closed(&self) -> bool {
+        matches!(
+            *self.state.authorization_state.read().unwrap(),
+            Some(AuthorizationState::Closed)
+        )
+    }
+
+    /// Create a new stream of the updates received for this client from now
+    /// on. Each stream receives every update independently from the others.
+    pub fn updates(&self) -> Updates {
+        let subscription = Arc::new(Subscription::new(
+            self.state.update_buffer,
+            self.state.overflow_policy,
+        ));
+        if self.is_closed() {
+            subscription.close();
+        } else {
+            self.state
+                .subscriptions
+                .lock()
+                .unwrap()
+                .push(subscription.clone());
+        }
+        Updates::new(subscription)
+    }
+
+    /// Send a request to TdLib with this client, applying the default
+    /// timeout and retry policy of the client, if any. Every method of the
+    /// client sends its request through here.
+    pub async fn send<F: Function>(&self, function: F) -> Result<F::Output, Error> {
+        if self.is_closed() {
+            return Err(Error::Closed);
+        }
+
+        let request = serde_json::to_string(&function).expect("requests are always serializable");
+        let response = match self.state.retry_policy {
+            Some(policy) if F::IDEMPOTENT => policy.run(|| self.send_request(&request)).await,
+            _ => self.send_request(&request).await,
+        };
+        F::parse_response(&response?)
+    }
+
+    async fn send_request(&self, request: &str) -> Result<String, Error> {
+        let request = self.runtime.send_request(self.id, request);
+        match self.state.request_timeout {
+            Some(duration) => timeout(duration, request).await,
+            None => request.await,
+        }
+    }
+
+    /// Get a stream of all the results of a paginated request, sending the
+    /// requests of the following pages as the stream is consumed. See the
+    /// `pagination` module.
+    pub fn paginate<F: Paginated>(&self, request: F) -> Paginator<'_, F> {
+        Paginator::new(self, request)
+    }
+
+    /// Synchronously execute a request with the backend of this client. Only
+    /// the functions documented with "Can be called synchronously" can be
+    /// executed.
+    pub fn execute<F: Function>(&self, function: F) -> Result<F::Output, Error> {
+        self.runtime.execute(function)
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.runtime.unregister_client(self.id);
+        if !self.is_closed() {
+            self.runtime
+                .backend()
+                .send(self.id, r#"{"@type":"close"}"#.into());
+        }
+
+        let mut subscriptions = self.state.subscriptions.lock().unwrap();
+        subscriptions.drain(..).for_each(|s| s.close());
+    }
+}
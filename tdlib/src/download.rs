@@ -0,0 +1,541 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Downloads of files, correlating `downloadFile` requests with the
+//! `updateFile` updates reporting their progress.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use std::sync::Arc;
+//! use tdlib::download::DownloadManager;
+//! use tdlib::Client;
+//!
+//! # async fn example(client: Client) -> Result<(), tdlib::Error> {
+//! let downloads = Arc::new(DownloadManager::new().max_concurrent_downloads(2));
+//!
+//! // The updates must be applied to the manager for downloads to complete
+//! let mut updates = client.updates();
+//! let updates_downloads = downloads.clone();
+//! tokio::spawn(async move {
+//!     while let Some(update) = updates.next().await {
+//!         updates_downloads.apply(&update);
+//!     }
+//! });
+//!
+//! let file = downloads.download(&client, 42, 1).await?;
+//! println!("Downloaded to {}", file.local.path);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::enums::{self, Update};
+use crate::types::File;
+use crate::updates::{OverflowPolicy, Subscription};
+use crate::{Client, Error};
+use futures_channel::oneshot;
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 4;
+const DEFAULT_PROGRESS_BUFFER: usize = 64;
+
+/// What the callers waiting for a download are told.
+enum Signal {
+    /// The caller must send the `downloadFile` request, with the highest
+    /// priority asked for the download.
+    Start {
+        priority: i32,
+    },
+    Completed(File),
+    Cancelled,
+}
+
+struct Download {
+    priority: i32,
+    /// The order in which the downloads were requested, among the ones with
+    /// the same priority.
+    sequence: u64,
+    active: bool,
+    waiters: Vec<oneshot::Sender<Signal>>,
+}
+
+#[derive(Default)]
+struct Downloads {
+    downloads: HashMap<i32, Download>,
+    progress: HashMap<i32, Vec<Arc<Subscription<File>>>>,
+    active: usize,
+    sequence: u64,
+}
+
+impl Downloads {
+    /// Ask one of the callers waiting for the download to start it, removing
+    /// the download if all of them gave up. Returns whether it was started.
+    fn start(&mut self, file_id: i32) -> bool {
+        let download = match self.downloads.get_mut(&file_id) {
+            Some(download) => download,
+            None => return false,
+        };
+
+        let priority = download.priority;
+        while !download.waiters.is_empty() {
+            if download
+                .waiters
+                .remove(0)
+                .send(Signal::Start { priority })
+                .is_ok()
+            {
+                return true;
+            }
+        }
+
+        self.downloads.remove(&file_id);
+        false
+    }
+
+    /// Start the queued downloads with the highest priority, as long as the
+    /// limit allows it.
+    fn start_queued(&mut self, max_concurrent_downloads: usize) {
+        while self.active < max_concurrent_downloads {
+            let next = self
+                .downloads
+                .iter()
+                .filter(|(_, download)| !download.active)
+                .max_by_key(|(_, download)| {
+                    (download.priority, std::cmp::Reverse(download.sequence))
+                })
+                .map(|(file_id, _)| *file_id);
+            let file_id = match next {
+                Some(file_id) => file_id,
+                None => return,
+            };
+
+            if self.start(file_id) {
+                self.downloads.get_mut(&file_id).unwrap().active = true;
+                self.active += 1;
+            }
+        }
+    }
+
+    /// Remove a download, telling all its callers, and close its progress
+    /// streams. Nothing happens if the file isn't downloaded by the manager.
+    fn finish(&mut self, file_id: i32, signal: impl Fn() -> Signal) {
+        let download = match self.downloads.remove(&file_id) {
+            Some(download) => download,
+            None => return,
+        };
+        if download.active {
+            self.active -= 1;
+        }
+        for waiter in download.waiters {
+            let _ = waiter.send(signal());
+        }
+        for subscription in self.progress.remove(&file_id).unwrap_or_default() {
+            subscription.close();
+        }
+    }
+}
+
+/// Hands the download over to another caller if the one which started it
+/// gives up before its completion.
+struct Driver<'a> {
+    manager: &'a DownloadManager,
+    file_id: i32,
+    done: bool,
+}
+
+impl Drop for Driver<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.manager.restart(self.file_id);
+        }
+    }
+}
+
+/// Downloads files, limiting the number of concurrent downloads and sharing
+/// the downloads of the same file between callers.
+///
+/// The manager must be fed every update of the client with `apply`, since
+/// the downloads are completed by the `updateFile` updates.
+pub struct DownloadManager {
+    downloads: Mutex<Downloads>,
+    max_concurrent_downloads: usize,
+}
+
+impl Default for DownloadManager {
+    fn default() -> Self {
+        DownloadManager {
+            downloads: Mutex::default(),
+            max_concurrent_downloads: DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+        }
+    }
+}
+
+impl DownloadManager {
+    /// Create a manager running up to 4 downloads at a time.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of downloads running at a time. The other
+    /// downloads are queued, and started by priority, then in the order they
+    /// were requested.
+    pub fn max_concurrent_downloads(mut self, max_concurrent_downloads: usize) -> Self {
+        self.max_concurrent_downloads = max_concurrent_downloads.max(1);
+        self
+    }
+
+    /// Download a file, returning it once its download is completed. The
+    /// priority, from 1 to 32, is used both to queue the download and by
+    /// TdLib; a higher value means a higher priority.
+    ///
+    /// Concurrent calls for the same file share a single download, whose
+    /// priority is raised if a call asks for a higher one. Dropping
+    /// the returned future doesn't stop the download, use `cancel` for that.
+    /// Fails with `Error::Cancelled` if the download is cancelled or stops
+    /// before being completed.
+    pub async fn download(
+        &self,
+        client: &Client,
+        file_id: i32,
+        priority: i32,
+    ) -> Result<File, Error> {
+        let (waiting, is_raised) = self.wait(file_id, priority);
+        if is_raised {
+            // Asking TdLib for the file again changes the priority of its
+            // running download
+            client.download_file(file_id, priority, 0, 0, false).await?;
+        }
+        let priority = match waiting.await {
+            Ok(Signal::Start { priority }) => priority,
+            Ok(Signal::Completed(file)) => return Ok(file),
+            Ok(Signal::Cancelled) | Err(_) => return Err(Error::Cancelled),
+        };
+
+        // This call sends the request, and the completion is received like
+        // for the other callers. The waiting starts before the request, in
+        // case the update completing the download arrives before its
+        // response. It must be dropped before the driver, so that the driver
+        // doesn't hand the download over to it when giving up.
+        let mut driver = Driver {
+            manager: self,
+            file_id,
+            done: false,
+        };
+        let (completion, _) = self.wait(file_id, priority);
+
+        // The pattern is refutable with the `unknown-variants` feature
+        #[allow(clippy::infallible_destructuring_match)]
+        let file = match client.download_file(file_id, priority, 0, 0, false).await? {
+            enums::File::File(file) => file,
+            #[cfg(feature = "unknown-variants")]
+            enums::File::Unrecognized(json) => return Err(Error::unrecognized(&json)),
+        };
+        if file.local.is_downloading_completed {
+            driver.done = true;
+            self.complete(file.clone());
+            return Ok(file);
+        }
+        if !file.local.is_downloading_active {
+            return Err(Error::Cancelled);
+        }
+
+        match completion.await {
+            Ok(Signal::Completed(file)) => {
+                driver.done = true;
+                Ok(file)
+            }
+            _ => Err(Error::Cancelled),
+        }
+    }
+
+    /// Cancel the download of a file, failing the calls waiting for it with
+    /// `Error::Cancelled`.
+    pub async fn cancel(&self, client: &Client, file_id: i32) -> Result<(), Error> {
+        {
+            let mut downloads = self.downloads.lock().unwrap();
+            downloads.finish(file_id, || Signal::Cancelled);
+            downloads.start_queued(self.max_concurrent_downloads);
+        }
+
+        client.cancel_download_file(file_id, false).await
+    }
+
+    /// Get a stream of the states of a file from now on, as reported by
+    /// `updateFile`. It ends once a download of the file by this manager is
+    /// completed or cancelled. Only the latest 64 states are buffered.
+    pub fn progress(&self, file_id: i32) -> FileProgress {
+        let subscription = Arc::new(Subscription::new(
+            DEFAULT_PROGRESS_BUFFER,
+            OverflowPolicy::DropOldest,
+        ));
+        self.downloads
+            .lock()
+            .unwrap()
+            .progress
+            .entry(file_id)
+            .or_default()
+            .push(subscription.clone());
+        FileProgress { subscription }
+    }
+
+    /// Update the downloads with an update of TdLib, ignoring the updates
+    /// which aren't about files.
+    pub fn apply(&self, update: &Update) {
+        let file = match update {
+            Update::File(update) => &update.file,
+            _ => return,
+        };
+
+        {
+            let mut downloads = self.downloads.lock().unwrap();
+            if let Some(subscriptions) = downloads.progress.get_mut(&file.id) {
+                subscriptions.retain(|subscription| subscription.push(file.clone()));
+            }
+        }
+
+        if file.local.is_downloading_completed {
+            self.complete(file.clone());
+        }
+    }
+
+    /// Wait for the download of a file, queuing it if needed. Also returns
+    /// whether the priority of the download was raised while it's running,
+    /// which must then be sent to TdLib.
+    fn wait(&self, file_id: i32, priority: i32) -> (oneshot::Receiver<Signal>, bool) {
+        let (sender, receiver) = oneshot::channel();
+        let mut downloads = self.downloads.lock().unwrap();
+        let sequence = downloads.sequence;
+        downloads.sequence += 1;
+
+        let download = downloads.downloads.entry(file_id).or_insert(Download {
+            priority,
+            sequence,
+            active: false,
+            waiters: Vec::new(),
+        });
+        let is_raised = download.active && priority > download.priority;
+        download.priority = download.priority.max(priority);
+        download.waiters.push(sender);
+
+        downloads.start_queued(self.max_concurrent_downloads);
+        (receiver, is_raised)
+    }
+
+    fn complete(&self, file: File) {
+        let mut downloads = self.downloads.lock().unwrap();
+        downloads.finish(file.id, || Signal::Completed(file.clone()));
+        downloads.start_queued(self.max_concurrent_downloads);
+    }
+
+    fn restart(&self, file_id: i32) {
+        let mut downloads = self.downloads.lock().unwrap();
+        let is_active = downloads
+            .downloads
+            .get(&file_id)
+            .is_some_and(|download| download.active);
+        if is_active && !downloads.start(file_id) {
+            downloads.active -= 1;
+            downloads.start_queued(self.max_concurrent_downloads);
+        }
+    }
+}
+
+/// A stream of the states of a file, returned by `DownloadManager::progress`.
+pub struct FileProgress {
+    subscription: Arc<Subscription<File>>,
+}
+
+impl Stream for FileProgress {
+    type Item = File;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.subscription.poll_next(cx)
+    }
+}
+
+impl Drop for FileProgress {
+    fn drop(&mut self) {
+        self.subscription.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBackend;
+    use crate::types::{LocalFile, RemoteFile, UpdateFile};
+    use futures_util::future::join;
+    use futures_util::{FutureExt, StreamExt};
+    use serde_json::{json, Value};
+
+    fn file(id: i32, downloaded_size: i64, is_downloading_completed: bool) -> File {
+        File {
+            id,
+            size: 100,
+            expected_size: 100,
+            local: LocalFile {
+                path: if is_downloading_completed {
+                    format!("/files/{}", id)
+                } else {
+                    String::new()
+                },
+                can_be_downloaded: true,
+                can_be_deleted: false,
+                is_downloading_active: !is_downloading_completed,
+                is_downloading_completed,
+                download_offset: 0,
+                downloaded_prefix_size: downloaded_size,
+                downloaded_size,
+            },
+            remote: RemoteFile {
+                id: String::new(),
+                unique_id: String::new(),
+                is_uploading_active: false,
+                is_uploading_completed: true,
+                uploaded_size: 100,
+            },
+        }
+    }
+
+    fn update(file: File) -> Update {
+        Update::File(UpdateFile { file })
+    }
+
+    /// Answer `downloadFile` with a file whose download just started, and
+    /// complete it with updates from another thread once `waiters` calls
+    /// wait for its completion.
+    fn respond_with_download(
+        mock: &Arc<MockBackend>,
+        manager: &Arc<DownloadManager>,
+        waiters: usize,
+    ) {
+        let manager = Arc::downgrade(manager);
+        mock.respond_with("downloadFile", move |request: &Value| {
+            let file_id = request["file_id"].as_i64().unwrap() as i32;
+            let manager = manager.upgrade().unwrap();
+            std::thread::spawn(move || {
+                while manager.downloads.lock().unwrap().downloads[&file_id]
+                    .waiters
+                    .len()
+                    < waiters
+                {
+                    std::thread::yield_now();
+                }
+                manager.apply(&update(file(file_id, 50, false)));
+                manager.apply(&update(file(file_id, 100, true)));
+            });
+            serde_json::to_value(enums::File::File(file(file_id, 0, false))).unwrap()
+        });
+    }
+
+    #[tokio::test]
+    async fn concurrent_downloads_are_shared() {
+        let mock = Arc::new(MockBackend::new());
+        let manager = Arc::new(DownloadManager::new());
+        respond_with_download(&mock, &manager, 2);
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let progress = manager.progress(7);
+        let (first, second) = join(
+            manager.download(&client, 7, 1),
+            manager.download(&client, 7, 1),
+        )
+        .await;
+
+        assert_eq!(first.unwrap().local.path, "/files/7");
+        assert_eq!(second.unwrap().local.path, "/files/7");
+        assert_eq!(mock.requests_of_type("downloadFile").len(), 1);
+
+        let sizes = progress
+            .map(|file| file.local.downloaded_size)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(sizes, vec![50, 100]);
+    }
+
+    #[tokio::test]
+    async fn raised_priorities_are_sent() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond("downloadFile", enums::File::File(file(8, 0, false)));
+        let manager = DownloadManager::new();
+        let client = Client::builder().backend(mock.clone()).build();
+
+        // Started by another caller, which didn't send its request yet
+        let (_started, _) = manager.wait(8, 1);
+        let (downloaded, ()) = join(manager.download(&client, 8, 10), async {
+            while mock.requests_of_type("downloadFile").is_empty() {
+                tokio::task::yield_now().await;
+            }
+            manager.apply(&update(file(8, 100, true)));
+        })
+        .await;
+
+        assert_eq!(downloaded.unwrap().local.path, "/files/8");
+        let requests = mock.requests_of_type("downloadFile");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["priority"], 10);
+    }
+
+    #[test]
+    fn progress_outlives_completions_of_other_downloads() {
+        let manager = DownloadManager::new();
+        let mut progress = manager.progress(9);
+        manager.apply(&update(file(9, 100, true)));
+        manager.apply(&update(file(9, 100, true)));
+
+        assert_eq!(
+            progress.next().now_or_never(),
+            Some(Some(file(9, 100, true)))
+        );
+        assert_eq!(
+            progress.next().now_or_never(),
+            Some(Some(file(9, 100, true)))
+        );
+        assert_eq!(progress.next().now_or_never(), None);
+    }
+
+    #[tokio::test]
+    async fn downloads_are_queued_by_priority() {
+        let manager = DownloadManager::new().max_concurrent_downloads(1);
+        let (mut first, _) = manager.wait(1, 1);
+        let (mut low, _) = manager.wait(2, 1);
+        let (mut high, _) = manager.wait(3, 10);
+        assert!(matches!(first.try_recv(), Ok(Some(Signal::Start { .. }))));
+        assert!(matches!(low.try_recv(), Ok(None)));
+        assert!(matches!(high.try_recv(), Ok(None)));
+
+        manager.apply(&update(file(1, 100, true)));
+        assert!(matches!(high.try_recv(), Ok(Some(Signal::Start { .. }))));
+        assert!(matches!(low.try_recv(), Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn cancel_fails_the_waiting_calls() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond("cancelDownloadFile", json!({"@type": "ok"}));
+        let manager = DownloadManager::new().max_concurrent_downloads(1);
+        let client = Client::builder().backend(mock.clone()).build();
+
+        let (mut active, _) = manager.wait(4, 1);
+        let (mut queued, _) = manager.wait(5, 1);
+        manager.cancel(&client, 5).await.unwrap();
+        assert!(matches!(queued.try_recv(), Ok(Some(Signal::Cancelled))));
+        assert!(matches!(active.try_recv(), Ok(Some(Signal::Start { .. }))));
+
+        manager.cancel(&client, 4).await.unwrap();
+        let downloads = manager.downloads.lock().unwrap();
+        assert!(downloads.downloads.is_empty());
+        assert_eq!(downloads.active, 0);
+        assert_eq!(mock.requests_of_type("cancelDownloadFile").len(), 2);
+    }
+}
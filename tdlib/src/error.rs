@@ -0,0 +1,264 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::types;
+use std::fmt;
+use std::time::Duration;
+
+/// An error that occurred while sending a request to TdLib.
+#[derive(Debug)]
+pub enum Error {
+    /// TdLib answered the request with an error.
+    Tdlib(types::Error),
+    /// The response couldn't be deserialized into the expected type. This
+    /// usually means that the TdLib version in use doesn't match the one the
+    /// crate was generated from.
+    Deserialize {
+        /// The raw JSON of the response.
+        json: String,
+        /// The reason why the deserialization failed.
+        source: serde_json::Error,
+    },
+    /// The request ended without receiving a response from TdLib.
+    Cancelled,
+    /// The request didn't receive a response in time.
+    Timeout,
+    /// The client the request was sent to is closed.
+    Closed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Tdlib(error) => write!(f, "{} (code {})", error.message, error.code),
+            Error::Deserialize { source, .. } => {
+                write!(f, "failed to deserialize the response: {}", source)
+            }
+            Error::Cancelled => write!(f, "the request ended without a response"),
+            Error::Timeout => write!(f, "the request timed out"),
+            Error::Closed => write!(f, "the client is closed"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Deserialize { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<types::Error> for Error {
+    fn from(error: types::Error) -> Self {
+        Error::Tdlib(error)
+    }
+}
+
+impl Error {
+    /// The category of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Tdlib(error) => error.kind(),
+            Error::Deserialize { .. } => ErrorKind::Deserialize,
+            Error::Cancelled => ErrorKind::Aborted,
+            Error::Timeout => ErrorKind::Timeout,
+            Error::Closed => ErrorKind::Closed,
+        }
+    }
+
+    /// The error of a response whose type is unknown to the crate, kept in
+    /// an `Unrecognized` variant.
+    #[cfg(feature = "unknown-variants")]
+    pub(crate) fn unrecognized(json: &serde_json::Value) -> Self {
+        Error::Deserialize {
+            json: json.to_string(),
+            source: serde::de::Error::custom(format!("unknown type {}", json["@type"])),
+        }
+    }
+}
+
+/// The category of an error, parsed from the code and the message of the
+/// errors returned by TdLib, so that they can be handled without matching
+/// the messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Too many requests were sent. The request can be sent again after
+    /// `retry_after`, if TdLib specified it.
+    RateLimited { retry_after: Option<Duration> },
+    /// The request has invalid parameters (code 400), like a wrong
+    /// authentication code.
+    InvalidArgument,
+    /// The client isn't authorized to send the request (code 401).
+    Unauthorized,
+    /// The request isn't allowed (code 403).
+    Forbidden,
+    /// The requested object doesn't exist (code 404).
+    NotFound,
+    /// The error must not be shown to the user (code 406).
+    Ignored,
+    /// Telegram couldn't be reached, or it's temporarily unavailable.
+    Network,
+    /// The request was aborted, usually because the client is closing.
+    Aborted,
+    /// The request didn't receive a response in time.
+    Timeout,
+    /// The client is closed.
+    Closed,
+    /// The response couldn't be deserialized.
+    Deserialize,
+    /// An internal error of TdLib or Telegram.
+    Internal,
+    /// Any other error.
+    Other,
+}
+
+impl ErrorKind {
+    /// Whether sending the same request again may succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ErrorKind::RateLimited { .. } | ErrorKind::Network | ErrorKind::Timeout
+        )
+    }
+}
+
+impl types::Error {
+    /// The category of the error.
+    pub fn kind(&self) -> ErrorKind {
+        if let Some(retry_after) = self.retry_after() {
+            return ErrorKind::RateLimited {
+                retry_after: Some(retry_after),
+            };
+        }
+
+        match (self.code, self.message.as_str()) {
+            (429 | 420, _) => ErrorKind::RateLimited { retry_after: None },
+            (_, "Request aborted") => ErrorKind::Aborted,
+            (_, message) if message.starts_with("NETWORK_") => ErrorKind::Network,
+            (400, _) => ErrorKind::InvalidArgument,
+            (401, _) => ErrorKind::Unauthorized,
+            (403, _) => ErrorKind::Forbidden,
+            (404, _) => ErrorKind::NotFound,
+            (406, _) => ErrorKind::Ignored,
+            (502..=504, _) => ErrorKind::Network,
+            (500..=599, _) => ErrorKind::Internal,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// The time to wait before sending the request again, for the errors
+    /// caused by too many requests, like `FLOOD_WAIT_30` or
+    /// `Too Many Requests: retry after 30`.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let seconds = self
+            .message
+            .strip_prefix("FLOOD_WAIT_")
+            .or_else(|| self.message.strip_prefix("Too Many Requests: retry after "))?;
+        seconds.trim().parse().ok().map(Duration::from_secs)
+    }
+}
+
+/// An error that occurred while loading TdLib at runtime.
+#[cfg(feature = "dlopen")]
+#[derive(Debug)]
+pub enum LoadError {
+    /// The library couldn't be opened.
+    Open {
+        /// The path the library was loaded from.
+        path: std::path::PathBuf,
+        /// The reason why the library couldn't be opened.
+        source: libloading::Error,
+    },
+    /// The library doesn't export a required function.
+    MissingSymbol {
+        /// The name of the missing function.
+        name: &'static str,
+        /// The reason why the function couldn't be found.
+        source: libloading::Error,
+    },
+    /// The library didn't report a valid version.
+    UnknownVersion,
+    /// The version of the library is older than the one the crate was
+    /// generated from.
+    UnsupportedVersion(String),
+}
+
+#[cfg(feature = "dlopen")]
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Open { path, source } => {
+                write!(f, "failed to open {}: {}", path.display(), source)
+            }
+            LoadError::MissingSymbol { name, .. } => {
+                write!(f, "the library doesn't export `{}`", name)
+            }
+            LoadError::UnknownVersion => write!(f, "the library didn't report its version"),
+            LoadError::UnsupportedVersion(version) => {
+                write!(f, "TdLib {} is not supported", version)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "dlopen")]
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Open { source, .. } | LoadError::MissingSymbol { source, .. } => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(code: i32, message: &str) -> Error {
+        Error::Tdlib(types::Error {
+            code,
+            message: message.into(),
+        })
+    }
+
+    #[test]
+    fn classify_rate_limits() {
+        let retry_after = Some(Duration::from_secs(30));
+        assert_eq!(
+            error(429, "Too Many Requests: retry after 30").kind(),
+            ErrorKind::RateLimited { retry_after }
+        );
+        assert_eq!(
+            error(420, "FLOOD_WAIT_30").kind(),
+            ErrorKind::RateLimited { retry_after }
+        );
+        assert_eq!(
+            error(429, "Too Many Requests").kind(),
+            ErrorKind::RateLimited { retry_after: None }
+        );
+    }
+
+    #[test]
+    fn classify_errors() {
+        assert_eq!(
+            error(400, "PHONE_CODE_INVALID").kind(),
+            ErrorKind::InvalidArgument
+        );
+        assert_eq!(error(401, "Unauthorized").kind(), ErrorKind::Unauthorized);
+        assert_eq!(error(404, "Not Found").kind(), ErrorKind::NotFound);
+        assert_eq!(error(500, "Request aborted").kind(), ErrorKind::Aborted);
+        assert_eq!(error(500, "Internal Error").kind(), ErrorKind::Internal);
+        assert_eq!(Error::Timeout.kind(), ErrorKind::Timeout);
+        assert!(!error(400, "CHAT_NOT_FOUND").kind().is_transient());
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{compare_entities, utf16_len};
+use crate::types::{FormattedText, TextEntity};
+use std::ops::Range;
+
+/// Helpers for the entities of a text, whose offsets and lengths are in
+/// UTF-16 code units while the text is in UTF-8.
+///
+/// # Examples
+///
+/// ```
+/// use tdlib::formatting::parse_markdown_v2;
+///
+/// let mut text = parse_markdown_v2("👋 *world*").unwrap();
+/// assert_eq!(text.entities[0].offset, 3);
+/// assert_eq!(text.entity_text(&text.entities[0]), Some("world"));
+///
+/// text.insert_text(0, "Hello ");
+/// assert_eq!(text.entities[0].offset, 9);
+/// assert_eq!(text.byte_range(9, 5), Some(11..16));
+/// ```
+impl FormattedText {
+    /// Convert an offset in UTF-16 code units to a byte offset in the text.
+    /// Returns `None` if it's past the end of the text or in the middle of a
+    /// character.
+    pub fn utf16_to_byte(&self, offset: i32) -> Option<usize> {
+        if offset < 0 {
+            return None;
+        }
+
+        let mut utf16_offset = 0;
+        for (byte, c) in self.text.char_indices() {
+            if utf16_offset >= offset {
+                return (utf16_offset == offset).then_some(byte);
+            }
+            utf16_offset += c.len_utf16() as i32;
+        }
+        (utf16_offset == offset).then_some(self.text.len())
+    }
+
+    /// Convert a byte offset in the text to an offset in UTF-16 code units.
+    /// Returns `None` if it's past the end of the text or not on a character
+    /// boundary.
+    pub fn byte_to_utf16(&self, byte: usize) -> Option<i32> {
+        self.text.get(..byte).map(utf16_len)
+    }
+
+    /// Convert an offset and a length in UTF-16 code units, like the ones of
+    /// an entity, to a range of bytes of the text.
+    pub fn byte_range(&self, offset: i32, length: i32) -> Option<Range<usize>> {
+        let start = self.utf16_to_byte(offset)?;
+        let end = self.utf16_to_byte(offset.checked_add(length)?)?;
+        (start <= end).then_some(start..end)
+    }
+
+    /// Convert a range of bytes of the text to an offset and a length in
+    /// UTF-16 code units, like the ones of an entity.
+    pub fn utf16_range(&self, range: Range<usize>) -> Option<(i32, i32)> {
+        let offset = self.byte_to_utf16(range.start)?;
+        let text = self.text.get(range)?;
+        Some((offset, utf16_len(text)))
+    }
+
+    /// Get the part of the text covered by an entity, or `None` if the
+    /// entity doesn't fit in the text.
+    pub fn entity_text(&self, entity: &TextEntity) -> Option<&str> {
+        self.byte_range(entity.offset, entity.length)
+            .map(|range| &self.text[range])
+    }
+
+    /// Iterate over the entities with the part of the text they cover,
+    /// skipping the entities which don't fit in the text.
+    pub fn entity_spans(&self) -> impl Iterator<Item = (&TextEntity, &str)> {
+        self.entities
+            .iter()
+            .filter_map(|entity| Some((entity, self.entity_text(entity)?)))
+    }
+
+    /// Insert text at a byte offset, moving the entities after it and
+    /// extending the ones around it. The entities starting at the offset are
+    /// moved rather than extended.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the offset isn't on a character boundary, like
+    /// `String::insert_str`.
+    pub fn insert_text(&mut self, byte: usize, text: &str) {
+        let offset = self
+            .byte_to_utf16(byte)
+            .expect("the offset must be on a character boundary");
+        self.text.insert_str(byte, text);
+
+        let length = utf16_len(text);
+        for entity in &mut self.entities {
+            if entity.offset >= offset {
+                entity.offset += length;
+            } else if entity.offset + entity.length > offset {
+                entity.length += length;
+            }
+        }
+    }
+
+    /// Remove a range of bytes of the text, shrinking or moving the entities
+    /// after its start. The entities which end up empty are removed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range isn't on character boundaries, like
+    /// `String::replace_range`.
+    pub fn remove_text(&mut self, range: Range<usize>) {
+        let (start, length) = self
+            .utf16_range(range.clone())
+            .expect("the range must be on character boundaries");
+        self.text.replace_range(range, "");
+
+        let end = start + length;
+        // Where a position ends up once the removed text is taken out
+        let shift = |position: i32| {
+            if position <= start {
+                position
+            } else if position >= end {
+                position - length
+            } else {
+                start
+            }
+        };
+        for entity in &mut self.entities {
+            let entity_end = shift(entity.offset + entity.length);
+            entity.offset = shift(entity.offset);
+            entity.length = entity_end - entity.offset;
+        }
+        self.entities.retain(|entity| entity.length > 0);
+    }
+
+    /// Merge the entities of the same type which overlap or touch each other,
+    /// and sort the entities like TdLib does.
+    pub fn merge_entities(&mut self) {
+        self.entities.sort_by(compare_entities);
+
+        let mut merged: Vec<TextEntity> = Vec::with_capacity(self.entities.len());
+        for entity in self.entities.drain(..) {
+            // Later entities of the same type start after the ones already
+            // merged, so only the last of them can overlap with this one
+            let previous = merged
+                .iter_mut()
+                .rev()
+                .find(|previous| previous.r#type == entity.r#type);
+            match previous {
+                Some(previous) if previous.offset + previous.length >= entity.offset => {
+                    let end =
+                        (previous.offset + previous.length).max(entity.offset + entity.length);
+                    previous.length = end - previous.offset;
+                }
+                _ => merged.push(entity),
+            }
+        }
+
+        merged.sort_by(compare_entities);
+        self.entities = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::enums::TextEntityType;
+    use crate::formatting::{parse_markdown_v2, FormattedTextBuilder};
+    use crate::types::TextEntity;
+
+    fn entity(offset: i32, length: i32, r#type: TextEntityType) -> TextEntity {
+        TextEntity {
+            offset,
+            length,
+            r#type,
+        }
+    }
+
+    #[test]
+    fn offsets_count_utf16_code_units() {
+        let text = parse_markdown_v2("é👍 *bold* end").unwrap();
+        assert_eq!(text.utf16_to_byte(1), Some(2));
+        // In the middle of the surrogate pair of 👍
+        assert_eq!(text.utf16_to_byte(2), None);
+        assert_eq!(text.utf16_to_byte(3), Some(6));
+        assert_eq!(text.utf16_to_byte(12), Some(text.text.len()));
+        assert_eq!(text.utf16_to_byte(13), None);
+        assert_eq!(text.byte_to_utf16(3), None);
+        assert_eq!(text.byte_to_utf16(7), Some(4));
+        assert_eq!(text.utf16_range(7..11), Some((4, 4)));
+
+        let spans = text
+            .entity_spans()
+            .map(|(entity, span)| (entity.r#type.clone(), span))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![(TextEntityType::Bold, "bold")]);
+    }
+
+    #[test]
+    fn editing_shifts_entities() {
+        let mut text = FormattedTextBuilder::new()
+            .bold("👍bold")
+            .text(" ")
+            .italic("italic")
+            .build();
+
+        // Inside the bold entity, and at the start of the italic one
+        text.insert_text(4, "💪");
+        text.insert_text(13, "very ");
+        assert_eq!(text.text, "👍💪bold very italic");
+        assert_eq!(text.entities[0], entity(0, 8, TextEntityType::Bold));
+        assert_eq!(text.entity_text(&text.entities[1]), Some("italic"));
+
+        // From the middle of the bold entity to the middle of the italic one
+        text.remove_text(10..20);
+        assert_eq!(text.text, "👍💪boalic");
+        assert_eq!(text.entities[0], entity(0, 6, TextEntityType::Bold));
+        assert_eq!(text.entities[1], entity(6, 4, TextEntityType::Italic));
+
+        text.remove_text(10..14);
+        assert_eq!(text.entities.len(), 1);
+    }
+
+    #[test]
+    fn merge_same_entities() {
+        let mut text = FormattedTextBuilder::new().text("0123456789").build();
+        text.entities = vec![
+            entity(6, 2, TextEntityType::Bold),
+            entity(0, 3, TextEntityType::Bold),
+            entity(1, 4, TextEntityType::Italic),
+            entity(2, 2, TextEntityType::Bold),
+            entity(4, 2, TextEntityType::Bold),
+        ];
+        text.merge_entities();
+        assert_eq!(
+            text.entities,
+            vec![
+                entity(0, 8, TextEntityType::Bold),
+                entity(1, 4, TextEntityType::Italic),
+            ]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{link_type, render, FormattedTextBuilder, Markup, ParseError};
+use crate::enums::TextEntityType;
+use crate::types::{FormattedText, TextEntityTypeCustomEmoji, TextEntityTypePreCode};
+
+enum Kind {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Spoiler,
+    /// Inline code, or the language of a block of code.
+    Code(String),
+    Pre(String),
+    Link(String),
+    CustomEmoji(i64),
+}
+
+struct Open {
+    tag: String,
+    kind: Kind,
+    /// The offset of the entity in the text, in UTF-16 code units.
+    offset: i32,
+    /// The byte offset of the tag in the parsed text.
+    tag_offset: usize,
+    /// The byte offset of the entity in the text.
+    text_offset: usize,
+}
+
+/// Decode the character reference at the start of `text`, returning the
+/// character and the length of the reference. Only the named references of
+/// `<`, `>`, `&` and `"` are supported, like in TdLib.
+fn decode_reference(text: &str) -> Option<(char, usize)> {
+    let name = text.strip_prefix('&')?;
+    let end = name.get(..11).unwrap_or(name).find(';')?;
+    let c = match &name[..end] {
+        "lt" => '<',
+        "gt" => '>',
+        "amp" => '&',
+        "quot" => '"',
+        name => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some((c, end + 2))
+}
+
+fn decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut position = 0;
+    while let Some(c) = text[position..].chars().next() {
+        let reference = match c {
+            '&' => decode_reference(&text[position..]),
+            _ => None,
+        };
+        match reference {
+            Some((c, length)) => {
+                decoded.push(c);
+                position += length;
+            }
+            None => {
+                decoded.push(c);
+                position += c.len_utf8();
+            }
+        }
+    }
+    decoded
+}
+
+/// A start tag, with its attributes, and the byte offset right after it.
+struct StartTag {
+    name: String,
+    attributes: Vec<(String, String)>,
+    end: usize,
+}
+
+impl StartTag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn parse_start_tag(text: &str, offset: usize) -> Result<StartTag, ParseError> {
+    let malformed = ParseError::MalformedTag { offset };
+    let skip = |position: usize, f: fn(char) -> bool| {
+        text[position..]
+            .find(|c| !f(c))
+            .map_or(text.len(), |length| position + length)
+    };
+
+    let mut position = skip(offset + 1, is_name_char);
+    let name = text[offset + 1..position].to_ascii_lowercase();
+    if name.is_empty() {
+        return Err(malformed);
+    }
+
+    let mut attributes = Vec::new();
+    loop {
+        position = skip(position, char::is_whitespace);
+        if text[position..].starts_with('>') {
+            return Ok(StartTag {
+                name,
+                attributes,
+                end: position + 1,
+            });
+        }
+
+        let start = position;
+        position = skip(position, is_name_char);
+        if position == start {
+            return Err(malformed);
+        }
+        let attribute = text[start..position].to_ascii_lowercase();
+
+        position = skip(position, char::is_whitespace);
+        let mut value = String::new();
+        if text[position..].starts_with('=') {
+            position = skip(position + 1, char::is_whitespace);
+            let raw = match text[position..].chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let length = text[position + 1..].find(quote).ok_or(malformed.clone())?;
+                    let raw = &text[position + 1..position + 1 + length];
+                    position += length + 2;
+                    raw
+                }
+                _ => {
+                    let start = position;
+                    position = skip(position, |c| !c.is_whitespace() && c != '>');
+                    &text[start..position]
+                }
+            };
+            value = decode(raw);
+        }
+        attributes.push((attribute, value));
+    }
+}
+
+/// Parse a text formatted with the HTML subset supported by Telegram, like
+/// `parseTextEntities` does with `textParseModeHTML`.
+///
+/// The supported tags are `<b>`, `<strong>`, `<i>`, `<em>`, `<u>`, `<ins>`,
+/// `<s>`, `<strike>`, `<del>`, `<tg-spoiler>`, `<span class="tg-spoiler">`,
+/// `<a href="...">`, `<tg-emoji emoji-id="...">`, `<code>` and `<pre>`, with
+/// the language of a block of code given by a `<code class="language-...">`
+/// inside its `<pre>`.
+pub fn parse_html(text: &str) -> Result<FormattedText, ParseError> {
+    let mut output = FormattedTextBuilder::new();
+    let mut stack: Vec<Open> = Vec::new();
+    let mut position = 0;
+    while let Some(c) = text[position..].chars().next() {
+        if c == '&' {
+            if let Some((c, length)) = decode_reference(&text[position..]) {
+                output.push_char(c);
+                position += length;
+                continue;
+            }
+        }
+        if c != '<' {
+            output.push_char(c);
+            position += c.len_utf8();
+            continue;
+        }
+
+        if text[position..].starts_with("</") {
+            let length = text[position..]
+                .find('>')
+                .ok_or(ParseError::MalformedTag { offset: position })?;
+            let tag = text[position + 2..position + length]
+                .trim()
+                .to_ascii_lowercase();
+            let open = match stack.pop() {
+                Some(open) if open.tag == tag => open,
+                _ => {
+                    return Err(ParseError::UnexpectedEndTag {
+                        tag,
+                        offset: position,
+                    })
+                }
+            };
+            position += length + 1;
+
+            let r#type = match open.kind {
+                Kind::Bold => Some(TextEntityType::Bold),
+                Kind::Italic => Some(TextEntityType::Italic),
+                Kind::Underline => Some(TextEntityType::Underline),
+                Kind::Strikethrough => Some(TextEntityType::Strikethrough),
+                Kind::Spoiler => Some(TextEntityType::Spoiler),
+                // The code inside a block of code only gives its language
+                Kind::Code(language) => match stack.last_mut() {
+                    Some(Open {
+                        kind: Kind::Pre(pre_language),
+                        ..
+                    }) => {
+                        if pre_language.is_empty() {
+                            *pre_language = language;
+                        }
+                        None
+                    }
+                    _ => Some(TextEntityType::Code),
+                },
+                Kind::Pre(language) if language.is_empty() => Some(TextEntityType::Pre),
+                Kind::Pre(language) => {
+                    Some(TextEntityType::PreCode(TextEntityTypePreCode { language }))
+                }
+                Kind::Link(url) if url.is_empty() => {
+                    link_type(output.as_str()[open.text_offset..].to_string())
+                }
+                Kind::Link(url) => link_type(url),
+                Kind::CustomEmoji(custom_emoji_id) => {
+                    Some(TextEntityType::CustomEmoji(TextEntityTypeCustomEmoji {
+                        custom_emoji_id,
+                    }))
+                }
+            };
+            if let Some(r#type) = r#type {
+                output.add_entity(open.offset, r#type);
+            }
+            continue;
+        }
+
+        let tag = parse_start_tag(text, position)?;
+        let kind = match tag.name.as_str() {
+            "b" | "strong" => Kind::Bold,
+            "i" | "em" => Kind::Italic,
+            "u" | "ins" => Kind::Underline,
+            "s" | "strike" | "del" => Kind::Strikethrough,
+            "tg-spoiler" => Kind::Spoiler,
+            "span" if tag.attribute("class") == Some("tg-spoiler") => Kind::Spoiler,
+            "a" => Kind::Link(tag.attribute("href").unwrap_or_default().into()),
+            "tg-emoji" => {
+                let id = tag.attribute("emoji-id").unwrap_or_default();
+                let custom_emoji_id = id.parse().map_err(|_| ParseError::InvalidValue {
+                    value: id.into(),
+                    offset: position,
+                })?;
+                Kind::CustomEmoji(custom_emoji_id)
+            }
+            "code" => Kind::Code(
+                tag.attribute("class")
+                    .and_then(|class| class.strip_prefix("language-"))
+                    .unwrap_or_default()
+                    .into(),
+            ),
+            "pre" => Kind::Pre(String::new()),
+            _ => {
+                return Err(ParseError::UnsupportedTag {
+                    tag: tag.name,
+                    offset: position,
+                })
+            }
+        };
+        stack.push(Open {
+            tag: tag.name,
+            kind,
+            offset: output.offset(),
+            tag_offset: position,
+            text_offset: output.as_str().len(),
+        });
+        position = tag.end;
+    }
+
+    if let Some(open) = stack.pop() {
+        return Err(ParseError::UnclosedEntity {
+            markup: format!("<{}>", open.tag),
+            offset: open.tag_offset,
+        });
+    }
+    Ok(output.build())
+}
+
+fn escape(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            c => output.push(c),
+        }
+    }
+}
+
+struct Html;
+
+impl Markup for Html {
+    const NESTS_IN_CODE: bool = true;
+
+    fn open(&mut self, r#type: &TextEntityType, output: &mut String) -> bool {
+        let tag = match r#type {
+            TextEntityType::Bold => "<b>",
+            TextEntityType::Italic => "<i>",
+            TextEntityType::Underline => "<u>",
+            TextEntityType::Strikethrough => "<s>",
+            TextEntityType::Spoiler => "<tg-spoiler>",
+            TextEntityType::Code => "<code>",
+            TextEntityType::Pre => "<pre>",
+            TextEntityType::PreCode(pre) => {
+                output.push_str("<pre><code class=\"language-");
+                escape(&pre.language, output);
+                output.push_str("\">");
+                return true;
+            }
+            TextEntityType::TextUrl(link) => {
+                output.push_str("<a href=\"");
+                escape(&link.url, output);
+                output.push_str("\">");
+                return true;
+            }
+            TextEntityType::MentionName(mention) => {
+                output.push_str(&format!("<a href=\"tg://user?id={}\">", mention.user_id));
+                return true;
+            }
+            TextEntityType::CustomEmoji(emoji) => {
+                output.push_str(&format!(
+                    "<tg-emoji emoji-id=\"{}\">",
+                    emoji.custom_emoji_id
+                ));
+                return true;
+            }
+            _ => return false,
+        };
+        output.push_str(tag);
+        true
+    }
+
+    fn close(&mut self, r#type: &TextEntityType, output: &mut String) {
+        output.push_str(match r#type {
+            TextEntityType::Bold => "</b>",
+            TextEntityType::Italic => "</i>",
+            TextEntityType::Underline => "</u>",
+            TextEntityType::Strikethrough => "</s>",
+            TextEntityType::Spoiler => "</tg-spoiler>",
+            TextEntityType::Code => "</code>",
+            TextEntityType::Pre => "</pre>",
+            TextEntityType::PreCode(_) => "</code></pre>",
+            TextEntityType::TextUrl(_) | TextEntityType::MentionName(_) => "</a>",
+            TextEntityType::CustomEmoji(_) => "</tg-emoji>",
+            _ => "",
+        });
+    }
+
+    fn escape(&mut self, c: char, _in_code: bool, output: &mut String) {
+        let mut buffer = [0; 4];
+        escape(c.encode_utf8(&mut buffer), output);
+    }
+}
+
+/// Render a text with the HTML subset supported by Telegram. The entities
+/// which can't be written in HTML, like the ones TdLib finds by itself in
+/// the text, are left out.
+pub fn to_html(text: &FormattedText) -> String {
+    render(text, &mut Html)
+}
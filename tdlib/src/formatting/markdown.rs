@@ -0,0 +1,345 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{link_type, render, FormattedTextBuilder, Markup, ParseError};
+use crate::enums::TextEntityType;
+use crate::types::{FormattedText, TextEntityTypeCustomEmoji, TextEntityTypePreCode};
+
+/// The characters which must be escaped outside of code.
+const RESERVED: &str = "_*[]()~`>#+-=|{}.!\\";
+
+enum Kind {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Spoiler,
+    Code,
+    Pre(String),
+    Link,
+    CustomEmoji,
+}
+
+impl Kind {
+    fn markup(&self) -> &'static str {
+        match self {
+            Kind::Bold => "*",
+            Kind::Italic => "_",
+            Kind::Underline => "__",
+            Kind::Strikethrough => "~",
+            Kind::Spoiler => "||",
+            Kind::Code => "`",
+            Kind::Pre(_) => "```",
+            Kind::Link => "[",
+            Kind::CustomEmoji => "![",
+        }
+    }
+}
+
+struct Open {
+    kind: Kind,
+    /// The offset of the entity in the text, in UTF-16 code units.
+    offset: i32,
+    /// The byte offset of the entity in the parsed text.
+    markup_offset: usize,
+    /// The byte offset of the entity in the text.
+    text_offset: usize,
+}
+
+/// Parse a text formatted with Telegram's MarkdownV2, like `parseTextEntities`
+/// does with `textParseModeMarkdown` version 2.
+///
+/// Any character with a code between 1 and 126 can be escaped with a
+/// preceding `\`, which is required for the characters with a meaning in the
+/// markup. A `\r` between a `_` and a `__` is ignored, to end an italic
+/// entity right before an underline one.
+pub fn parse_markdown_v2(text: &str) -> Result<FormattedText, ParseError> {
+    let chars = text.char_indices().collect::<Vec<_>>();
+    let char_at = |index: usize| chars.get(index).map(|(_, c)| *c);
+
+    let mut output = FormattedTextBuilder::new();
+    let mut stack: Vec<Open> = Vec::new();
+    let mut after_underscore = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+        let next = char_at(i + 1);
+        i += 1;
+
+        if c == '\r' && after_underscore && next == Some('_') {
+            continue;
+        }
+        after_underscore = false;
+
+        if c == '\\' {
+            if let Some(next) = next.filter(|next| (1..=126).contains(&(*next as u32))) {
+                output.push_char(next);
+                i += 1;
+                continue;
+            }
+        }
+
+        let top = stack.last().map(|open| &open.kind);
+        let in_code = matches!(top, Some(Kind::Code | Kind::Pre(_)));
+        let is_reserved = if in_code {
+            c == '`' || c == '\\'
+        } else {
+            RESERVED.contains(c)
+        };
+        if !is_reserved {
+            output.push_char(c);
+            continue;
+        }
+
+        let is_pre_marker = c == '`' && next == Some('`') && char_at(i + 1) == Some('`');
+        let closing_length = match (top, c) {
+            (Some(Kind::Bold), '*')
+            | (Some(Kind::Strikethrough), '~')
+            | (Some(Kind::Code), '`')
+            | (Some(Kind::Link | Kind::CustomEmoji), ']') => Some(1),
+            (Some(Kind::Italic), '_') if next != Some('_') => Some(1),
+            (Some(Kind::Underline), '_') if next == Some('_') => Some(2),
+            (Some(Kind::Spoiler), '|') if next == Some('|') => Some(2),
+            (Some(Kind::Pre(_)), '`') if is_pre_marker => Some(3),
+            _ => None,
+        };
+
+        if let Some(length) = closing_length {
+            i += length - 1;
+            after_underscore = c == '_';
+            let open = stack.pop().unwrap();
+            let r#type = match open.kind {
+                Kind::Bold => Some(TextEntityType::Bold),
+                Kind::Italic => Some(TextEntityType::Italic),
+                Kind::Underline => Some(TextEntityType::Underline),
+                Kind::Strikethrough => Some(TextEntityType::Strikethrough),
+                Kind::Spoiler => Some(TextEntityType::Spoiler),
+                Kind::Code => Some(TextEntityType::Code),
+                Kind::Pre(language) if language.is_empty() => Some(TextEntityType::Pre),
+                Kind::Pre(language) => {
+                    Some(TextEntityType::PreCode(TextEntityTypePreCode { language }))
+                }
+                Kind::Link => {
+                    let url = match parse_url(&chars, &mut i, open.markup_offset)? {
+                        Some(url) => url,
+                        None => output.as_str()[open.text_offset..].to_string(),
+                    };
+                    link_type(url)
+                }
+                Kind::CustomEmoji => {
+                    let url = parse_url(&chars, &mut i, open.markup_offset)?.unwrap_or_default();
+                    let custom_emoji_id = url
+                        .strip_prefix("tg://emoji?id=")
+                        .and_then(|id| id.parse().ok())
+                        .ok_or(ParseError::InvalidValue {
+                            value: url,
+                            offset: open.markup_offset,
+                        })?;
+                    Some(TextEntityType::CustomEmoji(TextEntityTypeCustomEmoji {
+                        custom_emoji_id,
+                    }))
+                }
+            };
+            if let Some(r#type) = r#type {
+                output.add_entity(open.offset, r#type);
+            }
+            continue;
+        }
+
+        let kind = match c {
+            _ if in_code => {
+                return Err(ParseError::ReservedCharacter {
+                    character: c,
+                    offset,
+                })
+            }
+            '*' => Kind::Bold,
+            '_' if next == Some('_') => Kind::Underline,
+            '_' => Kind::Italic,
+            '~' => Kind::Strikethrough,
+            '|' if next == Some('|') => Kind::Spoiler,
+            '[' => Kind::Link,
+            '!' if next == Some('[') => Kind::CustomEmoji,
+            '`' if is_pre_marker => Kind::Pre(String::new()),
+            '`' => Kind::Code,
+            _ => {
+                return Err(ParseError::ReservedCharacter {
+                    character: c,
+                    offset,
+                })
+            }
+        };
+        i += kind.markup().chars().count() - 1;
+        after_underscore = c == '_';
+
+        // The language of a block of code is the rest of its first line, if
+        // it's a single word
+        let kind = match kind {
+            Kind::Pre(_) => {
+                let end = (i..chars.len())
+                    .find(|index| {
+                        let c = chars[*index].1;
+                        c.is_whitespace() || c == '`'
+                    })
+                    .unwrap_or(chars.len());
+                if char_at(end) == Some('\n') {
+                    let language = chars[i..end].iter().map(|(_, c)| c).collect();
+                    i = end + 1;
+                    Kind::Pre(language)
+                } else {
+                    Kind::Pre(String::new())
+                }
+            }
+            kind => kind,
+        };
+
+        stack.push(Open {
+            kind,
+            offset: output.offset(),
+            markup_offset: offset,
+            text_offset: output.as_str().len(),
+        });
+    }
+
+    if let Some(open) = stack.pop() {
+        return Err(ParseError::UnclosedEntity {
+            markup: open.kind.markup().into(),
+            offset: open.markup_offset,
+        });
+    }
+    Ok(output.build())
+}
+
+/// Parse the URL following the text of a link, if any, where `)` and `\`
+/// must be escaped.
+fn parse_url(
+    chars: &[(usize, char)],
+    i: &mut usize,
+    offset: usize,
+) -> Result<Option<String>, ParseError> {
+    if chars.get(*i).map(|(_, c)| *c) != Some('(') {
+        return Ok(None);
+    }
+
+    let mut url = String::new();
+    *i += 1;
+    loop {
+        match chars.get(*i).map(|(_, c)| *c) {
+            Some(')') => {
+                *i += 1;
+                return Ok(Some(url));
+            }
+            Some('\\') if *i + 1 < chars.len() => {
+                url.push(chars[*i + 1].1);
+                *i += 2;
+            }
+            Some(c) => {
+                url.push(c);
+                *i += 1;
+            }
+            None => {
+                return Err(ParseError::UnclosedEntity {
+                    markup: "(".into(),
+                    offset,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct MarkdownV2 {
+    /// Whether the last thing written is a `_` starting or ending an entity.
+    after_underscore: bool,
+}
+
+impl MarkdownV2 {
+    fn write_markup(&mut self, markup: &str, output: &mut String) {
+        if self.after_underscore && markup.starts_with('_') {
+            output.push('\r');
+        }
+        output.push_str(markup);
+        self.after_underscore = markup.ends_with('_');
+    }
+
+    fn write_url(&mut self, url: &str, output: &mut String) {
+        output.push_str("](");
+        for c in url.chars() {
+            if c == ')' || c == '\\' {
+                output.push('\\');
+            }
+            output.push(c);
+        }
+        output.push(')');
+        self.after_underscore = false;
+    }
+}
+
+impl Markup for MarkdownV2 {
+    const NESTS_IN_CODE: bool = false;
+
+    fn open(&mut self, r#type: &TextEntityType, output: &mut String) -> bool {
+        let markup = match r#type {
+            TextEntityType::Bold => "*".into(),
+            TextEntityType::Italic => "_".into(),
+            TextEntityType::Underline => "__".into(),
+            TextEntityType::Strikethrough => "~".into(),
+            TextEntityType::Spoiler => "||".into(),
+            TextEntityType::Code => "`".into(),
+            TextEntityType::Pre => "```\n".into(),
+            TextEntityType::PreCode(pre) => format!("```{}\n", pre.language),
+            TextEntityType::TextUrl(_) | TextEntityType::MentionName(_) => "[".into(),
+            TextEntityType::CustomEmoji(_) => "![".into(),
+            _ => return false,
+        };
+        self.write_markup(&markup, output);
+        true
+    }
+
+    fn close(&mut self, r#type: &TextEntityType, output: &mut String) {
+        let markup = match r#type {
+            TextEntityType::Bold => "*",
+            TextEntityType::Italic => "_",
+            TextEntityType::Underline => "__",
+            TextEntityType::Strikethrough => "~",
+            TextEntityType::Spoiler => "||",
+            TextEntityType::Code => "`",
+            TextEntityType::Pre | TextEntityType::PreCode(_) => "```",
+            TextEntityType::TextUrl(link) => return self.write_url(&link.url, output),
+            TextEntityType::MentionName(mention) => {
+                let url = format!("tg://user?id={}", mention.user_id);
+                return self.write_url(&url, output);
+            }
+            TextEntityType::CustomEmoji(emoji) => {
+                let url = format!("tg://emoji?id={}", emoji.custom_emoji_id);
+                return self.write_url(&url, output);
+            }
+            _ => return,
+        };
+        self.write_markup(markup, output);
+    }
+
+    fn escape(&mut self, c: char, in_code: bool, output: &mut String) {
+        let is_reserved = if in_code {
+            c == '`' || c == '\\'
+        } else {
+            RESERVED.contains(c)
+        };
+        if is_reserved {
+            output.push('\\');
+        }
+        output.push(c);
+        self.after_underscore = false;
+    }
+}
+
+/// Render a text with Telegram's MarkdownV2. The entities which can't be
+/// written in MarkdownV2, like the ones TdLib finds by itself in the text,
+/// are left out, as well as the entities inside code.
+pub fn to_markdown_v2(text: &FormattedText) -> String {
+    render(text, &mut MarkdownV2::default())
+}
@@ -0,0 +1,655 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Building `FormattedText`s without a client, from a typed DSL, from
+//! Telegram's [MarkdownV2] or from its [HTML subset], and rendering them back
+//! to those formats.
+//!
+//! The offsets and lengths of the entities are computed in UTF-16 code
+//! units, like TdLib does, and the entities are sorted like the ones returned
+//! by `parseTextEntities`. The entities of an existing `FormattedText` can
+//! be handled with its methods converting between UTF-16 offsets and byte
+//! offsets.
+//!
+//! [MarkdownV2]: https://core.telegram.org/bots/api#markdownv2-style
+//! [HTML subset]: https://core.telegram.org/bots/api#html-style
+//!
+//! # Examples
+//!
+//! ```
+//! use tdlib::formatting::{parse_markdown_v2, to_html, FormattedTextBuilder};
+//!
+//! let text = FormattedTextBuilder::new()
+//!     .text("Read the ")
+//!     .text_url("documentation", "https://docs.rs/tdlib")
+//!     .text(", ")
+//!     .bold("now")
+//!     .build();
+//! assert_eq!(
+//!     to_html(&text),
+//!     "Read the <a href=\"https://docs.rs/tdlib\">documentation</a>, <b>now</b>"
+//! );
+//! assert_eq!(
+//!     parse_markdown_v2("Read the [documentation](https://docs.rs/tdlib), *now*").unwrap(),
+//!     text
+//! );
+//! ```
+mod entities;
+mod html;
+mod markdown;
+
+pub use html::{parse_html, to_html};
+pub use markdown::{parse_markdown_v2, to_markdown_v2};
+
+use crate::enums::TextEntityType;
+use crate::types::{
+    FormattedText, TextEntity, TextEntityTypeCustomEmoji, TextEntityTypeMentionName,
+    TextEntityTypePreCode, TextEntityTypeTextUrl,
+};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// An error in the markup of a text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// A character with a meaning in the markup is used as plain text without
+    /// being escaped.
+    ReservedCharacter {
+        character: char,
+        /// The byte offset of the character in the text.
+        offset: usize,
+    },
+    /// An entity is never closed.
+    UnclosedEntity {
+        /// The markup of the entity, like `*` or `<b>`.
+        markup: String,
+        /// The byte offset where the entity starts in the text.
+        offset: usize,
+    },
+    /// A link to a custom emoji or an attribute has an invalid value.
+    InvalidValue {
+        value: String,
+        /// The byte offset of the value, or of its entity, in the text.
+        offset: usize,
+    },
+    /// An HTML tag isn't supported.
+    UnsupportedTag {
+        tag: String,
+        /// The byte offset of the tag in the text.
+        offset: usize,
+    },
+    /// An HTML end tag doesn't match the last open tag.
+    UnexpectedEndTag {
+        tag: String,
+        /// The byte offset of the tag in the text.
+        offset: usize,
+    },
+    /// An HTML tag isn't well-formed.
+    MalformedTag {
+        /// The byte offset of the tag in the text.
+        offset: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::ReservedCharacter { character, offset } => write!(
+                f,
+                "character '{}' at byte offset {} is reserved and must be escaped",
+                character, offset
+            ),
+            ParseError::UnclosedEntity { markup, offset } => write!(
+                f,
+                "can't find the end of the entity started by `{}` at byte offset {}",
+                markup, offset
+            ),
+            ParseError::InvalidValue { value, offset } => {
+                write!(f, "invalid value \"{}\" at byte offset {}", value, offset)
+            }
+            ParseError::UnsupportedTag { tag, offset } => {
+                write!(f, "unsupported tag <{}> at byte offset {}", tag, offset)
+            }
+            ParseError::UnexpectedEndTag { tag, offset } => {
+                write!(f, "unexpected end tag </{}> at byte offset {}", tag, offset)
+            }
+            ParseError::MalformedTag { offset } => {
+                write!(f, "malformed tag at byte offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<&str> for FormattedText {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+impl From<String> for FormattedText {
+    /// A text without entities.
+    fn from(text: String) -> Self {
+        FormattedText {
+            text,
+            entities: Vec::new(),
+        }
+    }
+}
+
+/// The length of a text in UTF-16 code units, the unit of the offsets and
+/// lengths of the entities.
+pub fn utf16_len(text: &str) -> i32 {
+    text.encode_utf16().count() as i32
+}
+
+/// The priority of the entities starting at the same offset with the same
+/// length, from TdLib, the lowest being the outermost.
+fn type_priority(r#type: &TextEntityType) -> u8 {
+    match r#type {
+        TextEntityType::PreCode(_) => 10,
+        TextEntityType::Pre => 11,
+        TextEntityType::Code => 20,
+        TextEntityType::TextUrl(_) | TextEntityType::MentionName(_) => 49,
+        TextEntityType::Bold => 90,
+        TextEntityType::Italic => 91,
+        TextEntityType::Underline => 92,
+        TextEntityType::Strikethrough => 93,
+        TextEntityType::Spoiler => 94,
+        TextEntityType::CustomEmoji(_) => 99,
+        _ => 50,
+    }
+}
+
+/// Compare entities in the order of TdLib: by offset, then from the longest,
+/// so that the entities containing others come first.
+pub(crate) fn compare_entities(a: &TextEntity, b: &TextEntity) -> Ordering {
+    a.offset
+        .cmp(&b.offset)
+        .then(b.length.cmp(&a.length))
+        .then(type_priority(&a.r#type).cmp(&type_priority(&b.r#type)))
+}
+
+/// Whether an entity is a code block, whose content is shown verbatim.
+pub(crate) fn is_code(r#type: &TextEntityType) -> bool {
+    matches!(
+        r#type,
+        TextEntityType::Code | TextEntityType::Pre | TextEntityType::PreCode(_)
+    )
+}
+
+/// The type of the entity of a link, from its URL.
+fn link_type(url: String) -> Option<TextEntityType> {
+    if let Some(user_id) = url
+        .strip_prefix("tg://user?id=")
+        .and_then(|id| id.parse().ok())
+    {
+        return Some(TextEntityType::MentionName(TextEntityTypeMentionName {
+            user_id,
+        }));
+    }
+    (!url.is_empty()).then_some(TextEntityType::TextUrl(TextEntityTypeTextUrl { url }))
+}
+
+/// Builds a `FormattedText` a piece at a time, computing the offsets of the
+/// entities.
+///
+/// # Examples
+///
+/// ```
+/// use tdlib::enums::TextEntityType;
+/// use tdlib::formatting::{to_markdown_v2, FormattedTextBuilder};
+///
+/// let text = FormattedTextBuilder::new()
+///     .nested(TextEntityType::Bold, |text| text.text("bold and ").italic("italic"))
+///     .text(" 👋")
+///     .build();
+/// assert_eq!(text.entities[1].length, 6);
+/// assert_eq!(to_markdown_v2(&text), "*bold and _italic_* 👋");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FormattedTextBuilder {
+    text: String,
+    entities: Vec<TextEntity>,
+    /// The length of the text in UTF-16 code units.
+    offset: i32,
+}
+
+impl FormattedTextBuilder {
+    /// Create a builder of an empty text.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add plain text.
+    pub fn text(mut self, text: &str) -> Self {
+        self.push_str(text);
+        self
+    }
+
+    /// Add text covered by an entity of the given type.
+    pub fn entity(self, r#type: TextEntityType, text: &str) -> Self {
+        self.nested(r#type, |builder| builder.text(text))
+    }
+
+    /// Add the pieces added by `build` covered by an entity of the given
+    /// type, which may contain other entities.
+    pub fn nested(mut self, r#type: TextEntityType, build: impl FnOnce(Self) -> Self) -> Self {
+        let offset = self.offset;
+        self = build(self);
+        self.add_entity(offset, r#type);
+        self
+    }
+
+    /// Add bold text.
+    pub fn bold(self, text: &str) -> Self {
+        self.entity(TextEntityType::Bold, text)
+    }
+
+    /// Add italic text.
+    pub fn italic(self, text: &str) -> Self {
+        self.entity(TextEntityType::Italic, text)
+    }
+
+    /// Add underlined text.
+    pub fn underline(self, text: &str) -> Self {
+        self.entity(TextEntityType::Underline, text)
+    }
+
+    /// Add strikethrough text.
+    pub fn strikethrough(self, text: &str) -> Self {
+        self.entity(TextEntityType::Strikethrough, text)
+    }
+
+    /// Add text hidden by a spoiler.
+    pub fn spoiler(self, text: &str) -> Self {
+        self.entity(TextEntityType::Spoiler, text)
+    }
+
+    /// Add inline code.
+    pub fn code(self, text: &str) -> Self {
+        self.entity(TextEntityType::Code, text)
+    }
+
+    /// Add a block of code, in the given programming language if it's not
+    /// empty.
+    pub fn pre(self, text: &str, language: &str) -> Self {
+        let r#type = if language.is_empty() {
+            TextEntityType::Pre
+        } else {
+            TextEntityType::PreCode(TextEntityTypePreCode {
+                language: language.into(),
+            })
+        };
+        self.entity(r#type, text)
+    }
+
+    /// Add text opening a URL when clicked.
+    pub fn text_url(self, text: &str, url: &str) -> Self {
+        self.entity(
+            TextEntityType::TextUrl(TextEntityTypeTextUrl { url: url.into() }),
+            text,
+        )
+    }
+
+    /// Add a mention of a user by its identifier.
+    pub fn mention_name(self, text: &str, user_id: i64) -> Self {
+        self.entity(
+            TextEntityType::MentionName(TextEntityTypeMentionName { user_id }),
+            text,
+        )
+    }
+
+    /// Add a custom emoji, whose text must be the emoji it replaces.
+    pub fn custom_emoji(self, text: &str, custom_emoji_id: i64) -> Self {
+        self.entity(
+            TextEntityType::CustomEmoji(TextEntityTypeCustomEmoji { custom_emoji_id }),
+            text,
+        )
+    }
+
+    /// Get the text, with its entities sorted like TdLib does.
+    pub fn build(mut self) -> FormattedText {
+        self.entities.sort_by(compare_entities);
+        FormattedText {
+            text: self.text,
+            entities: self.entities,
+        }
+    }
+
+    pub(crate) fn push_char(&mut self, c: char) {
+        self.text.push(c);
+        self.offset += c.len_utf16() as i32;
+    }
+
+    pub(crate) fn push_str(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.offset += utf16_len(text);
+    }
+
+    /// The current length of the text, in UTF-16 code units.
+    pub(crate) fn offset(&self) -> i32 {
+        self.offset
+    }
+
+    /// The text added so far.
+    pub(crate) fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Add an entity from `offset` to the end of the text, unless it would
+    /// be empty.
+    pub(crate) fn add_entity(&mut self, offset: i32, r#type: TextEntityType) {
+        if self.offset > offset {
+            self.entities.push(TextEntity {
+                offset,
+                length: self.offset - offset,
+                r#type,
+            });
+        }
+    }
+}
+
+/// A markup the entities of a text can be rendered to.
+trait Markup {
+    /// Whether entities can be rendered inside code blocks.
+    const NESTS_IN_CODE: bool;
+
+    /// Write the start of an entity, returning `false` without writing
+    /// anything if the markup doesn't support it.
+    fn open(&mut self, r#type: &TextEntityType, output: &mut String) -> bool;
+
+    /// Write the end of an entity.
+    fn close(&mut self, r#type: &TextEntityType, output: &mut String);
+
+    /// Write a character of the text, escaped if needed.
+    fn escape(&mut self, c: char, in_code: bool, output: &mut String);
+}
+
+/// Render a text with a markup. The entities overlapping without being
+/// nested are split, closing and opening them again around the entities
+/// ending before them.
+fn render<M: Markup>(text: &FormattedText, markup: &mut M) -> String {
+    let mut entities = text
+        .entities
+        .iter()
+        .filter(|entity| entity.length > 0)
+        .collect::<Vec<_>>();
+    entities.sort_by(|a, b| compare_entities(a, b));
+    let mut entities = entities.into_iter().peekable();
+
+    let mut output = String::with_capacity(text.text.len());
+    let mut stack: Vec<&TextEntity> = Vec::new();
+    let mut offset = 0;
+    let mut chars = text.text.chars();
+    loop {
+        while let Some(index) = stack
+            .iter()
+            .position(|entity| entity.offset + entity.length <= offset)
+        {
+            let reopened = stack.split_off(index + 1);
+            for entity in reopened.iter().rev() {
+                markup.close(&entity.r#type, &mut output);
+            }
+            let ended = stack.pop().unwrap();
+            markup.close(&ended.r#type, &mut output);
+            for entity in reopened {
+                if entity.offset + entity.length > offset
+                    && markup.open(&entity.r#type, &mut output)
+                {
+                    stack.push(entity);
+                }
+            }
+        }
+
+        while let Some(entity) = entities.next_if(|entity| entity.offset <= offset) {
+            let in_code = stack.iter().any(|entity| is_code(&entity.r#type));
+            if entity.offset + entity.length > offset
+                && (M::NESTS_IN_CODE || !in_code)
+                && markup.open(&entity.r#type, &mut output)
+            {
+                stack.push(entity);
+            }
+        }
+
+        let c = match chars.next() {
+            Some(c) => c,
+            None => break,
+        };
+        let in_code = stack.iter().any(|entity| is_code(&entity.r#type));
+        markup.escape(c, in_code, &mut output);
+        offset += c.len_utf16() as i32;
+    }
+
+    while let Some(entity) = stack.pop() {
+        markup.close(&entity.r#type, &mut output);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Texts with the `formattedText` returned by `parseTextEntities` for
+    /// them, with `textParseModeMarkdown` version 2 or `textParseModeHTML`.
+    fn parsed(json: serde_json::Value) -> FormattedText {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn markdown_fixtures() -> Vec<(&'static str, FormattedText)> {
+        vec![
+            (
+                "*bold* _italic_ __underline__ ~strikethrough~ ||spoiler||",
+                parsed(json!({
+                    "text": "bold italic underline strikethrough spoiler",
+                    "entities": [
+                        {"offset": 0, "length": 4, "type": {"@type": "textEntityTypeBold"}},
+                        {"offset": 5, "length": 6, "type": {"@type": "textEntityTypeItalic"}},
+                        {"offset": 12, "length": 9, "type": {"@type": "textEntityTypeUnderline"}},
+                        {"offset": 22, "length": 13, "type": {"@type": "textEntityTypeStrikethrough"}},
+                        {"offset": 36, "length": 7, "type": {"@type": "textEntityTypeSpoiler"}}
+                    ]
+                })),
+            ),
+            (
+                "*bold _italic bold ~italic bold strikethrough ||italic bold strikethrough spoiler||~ __underline italic bold___ bold*",
+                parsed(json!({
+                    "text": "bold italic bold italic bold strikethrough italic bold strikethrough spoiler underline italic bold bold",
+                    "entities": [
+                        {"offset": 0, "length": 103, "type": {"@type": "textEntityTypeBold"}},
+                        {"offset": 5, "length": 93, "type": {"@type": "textEntityTypeItalic"}},
+                        {"offset": 17, "length": 59, "type": {"@type": "textEntityTypeStrikethrough"}},
+                        {"offset": 43, "length": 33, "type": {"@type": "textEntityTypeSpoiler"}},
+                        {"offset": 77, "length": 21, "type": {"@type": "textEntityTypeUnderline"}}
+                    ]
+                })),
+            ),
+            (
+                "*👍 héllo* `a\\`b` _*both*_",
+                parsed(json!({
+                    "text": "👍 héllo a`b both",
+                    "entities": [
+                        {"offset": 0, "length": 8, "type": {"@type": "textEntityTypeBold"}},
+                        {"offset": 9, "length": 3, "type": {"@type": "textEntityTypeCode"}},
+                        {"offset": 13, "length": 4, "type": {"@type": "textEntityTypeBold"}},
+                        {"offset": 13, "length": 4, "type": {"@type": "textEntityTypeItalic"}}
+                    ]
+                })),
+            ),
+            (
+                "[link](https://example.com/) [user](tg://user?id=123456) ![👍](tg://emoji?id=5368324170671202286)",
+                parsed(json!({
+                    "text": "link user 👍",
+                    "entities": [
+                        {"offset": 0, "length": 4, "type": {"@type": "textEntityTypeTextUrl", "url": "https://example.com/"}},
+                        {"offset": 5, "length": 4, "type": {"@type": "textEntityTypeMentionName", "user_id": 123456}},
+                        {"offset": 10, "length": 2, "type": {"@type": "textEntityTypeCustomEmoji", "custom_emoji_id": "5368324170671202286"}}
+                    ]
+                })),
+            ),
+            (
+                "```rust\nfn main() {}``` and ```\nplain```",
+                parsed(json!({
+                    "text": "fn main() {} and plain",
+                    "entities": [
+                        {"offset": 0, "length": 12, "type": {"@type": "textEntityTypePreCode", "language": "rust"}},
+                        {"offset": 17, "length": 5, "type": {"@type": "textEntityTypePre"}}
+                    ]
+                })),
+            ),
+            (
+                "1\\.5 \\- \\*not bold\\* \\\\",
+                parsed(json!({"text": "1.5 - *not bold* \\", "entities": []})),
+            ),
+        ]
+    }
+
+    fn html_fixtures() -> Vec<(&'static str, FormattedText)> {
+        vec![
+            (
+                "<b>bold</b>, <STRONG>strong</STRONG>, <i>i</i><em>em</em>, <u>u</u><ins>ins</ins>, <s>s</s><del>del</del>",
+                parsed(json!({
+                    "text": "bold, strong, iem, uins, sdel",
+                    "entities": [
+                        {"offset": 0, "length": 4, "type": {"@type": "textEntityTypeBold"}},
+                        {"offset": 6, "length": 6, "type": {"@type": "textEntityTypeBold"}},
+                        {"offset": 14, "length": 1, "type": {"@type": "textEntityTypeItalic"}},
+                        {"offset": 15, "length": 2, "type": {"@type": "textEntityTypeItalic"}},
+                        {"offset": 19, "length": 1, "type": {"@type": "textEntityTypeUnderline"}},
+                        {"offset": 20, "length": 3, "type": {"@type": "textEntityTypeUnderline"}},
+                        {"offset": 25, "length": 1, "type": {"@type": "textEntityTypeStrikethrough"}},
+                        {"offset": 26, "length": 3, "type": {"@type": "textEntityTypeStrikethrough"}}
+                    ]
+                })),
+            ),
+            (
+                "<a href=\"https://example.com/?a=1&amp;b=2\">link</a> <a href='tg://user?id=42'>user</a> <tg-emoji emoji-id=\"5368324170671202286\">👍</tg-emoji>",
+                parsed(json!({
+                    "text": "link user 👍",
+                    "entities": [
+                        {"offset": 0, "length": 4, "type": {"@type": "textEntityTypeTextUrl", "url": "https://example.com/?a=1&b=2"}},
+                        {"offset": 5, "length": 4, "type": {"@type": "textEntityTypeMentionName", "user_id": 42}},
+                        {"offset": 10, "length": 2, "type": {"@type": "textEntityTypeCustomEmoji", "custom_emoji_id": "5368324170671202286"}}
+                    ]
+                })),
+            ),
+            (
+                "<code>a &lt; b &amp;&amp; c &gt; d</code> <pre><code class=\"language-python\">print(1)</code></pre> <pre>raw</pre> &#128077;",
+                parsed(json!({
+                    "text": "a < b && c > d print(1) raw 👍",
+                    "entities": [
+                        {"offset": 0, "length": 14, "type": {"@type": "textEntityTypeCode"}},
+                        {"offset": 15, "length": 8, "type": {"@type": "textEntityTypePreCode", "language": "python"}},
+                        {"offset": 24, "length": 3, "type": {"@type": "textEntityTypePre"}}
+                    ]
+                })),
+            ),
+            (
+                "<tg-spoiler>one</tg-spoiler> <span class=\"tg-spoiler\">two <b>bold</b></span>",
+                parsed(json!({
+                    "text": "one two bold",
+                    "entities": [
+                        {"offset": 0, "length": 3, "type": {"@type": "textEntityTypeSpoiler"}},
+                        {"offset": 4, "length": 8, "type": {"@type": "textEntityTypeSpoiler"}},
+                        {"offset": 8, "length": 4, "type": {"@type": "textEntityTypeBold"}}
+                    ]
+                })),
+            ),
+        ]
+    }
+
+    #[test]
+    fn markdown_like_tdlib() {
+        for (markdown, expected) in markdown_fixtures() {
+            assert_eq!(
+                parse_markdown_v2(markdown).unwrap(),
+                expected,
+                "{}",
+                markdown
+            );
+            let rendered = to_markdown_v2(&expected);
+            assert_eq!(
+                parse_markdown_v2(&rendered).unwrap(),
+                expected,
+                "{}",
+                rendered
+            );
+        }
+    }
+
+    #[test]
+    fn html_like_tdlib() {
+        for (html, expected) in html_fixtures() {
+            assert_eq!(parse_html(html).unwrap(), expected, "{}", html);
+            let rendered = to_html(&expected);
+            assert_eq!(parse_html(&rendered).unwrap(), expected, "{}", rendered);
+        }
+    }
+
+    #[test]
+    fn markup_errors() {
+        assert_eq!(
+            parse_markdown_v2("version 1.0"),
+            Err(ParseError::ReservedCharacter {
+                character: '.',
+                offset: 9
+            })
+        );
+        assert_eq!(
+            parse_markdown_v2("ok *bold"),
+            Err(ParseError::UnclosedEntity {
+                markup: "*".into(),
+                offset: 3
+            })
+        );
+        assert_eq!(
+            parse_html("<b>bold</i>"),
+            Err(ParseError::UnexpectedEndTag {
+                tag: "i".into(),
+                offset: 7
+            })
+        );
+        assert_eq!(
+            parse_html("<marquee>old</marquee>"),
+            Err(ParseError::UnsupportedTag {
+                tag: "marquee".into(),
+                offset: 0
+            })
+        );
+    }
+
+    #[test]
+    fn non_ascii_attributes() {
+        let expected = parsed(json!({
+            "text": "x",
+            "entities": [
+                {"offset": 0, "length": 1, "type": {"@type": "textEntityTypeTextUrl", "url": "https://ex.com/é;"}}
+            ]
+        }));
+        assert_eq!(
+            parse_html("<a href=\"https://ex.com/é;\">x</a>").unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn overlapping_entities_are_split() {
+        let text = parsed(json!({
+            "text": "abcdef",
+            "entities": [
+                {"offset": 0, "length": 4, "type": {"@type": "textEntityTypeBold"}},
+                {"offset": 2, "length": 4, "type": {"@type": "textEntityTypeItalic"}}
+            ]
+        }));
+        assert_eq!(to_html(&text), "<b>ab<i>cd</i></b><i>ef</i>");
+        assert_eq!(to_markdown_v2(&text), "*ab_cd_*_ef_");
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::Error;
+use serde::Serialize;
+
+/// A TdLib function, which can be sent as a request with `Client::send`.
+///
+/// It's implemented by the request structs of the `functions` module, which
+/// serialize to the JSON object expected by TdLib, so requests can be stored,
+/// logged or inspected before sending them.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn example(client: tdlib::Client) -> Result<(), tdlib::Error> {
+/// use tdlib::functions::GetChats;
+///
+/// let request = GetChats {
+///     chat_list: None,
+///     limit: 20,
+/// };
+/// let chats = client.send(request).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait Function: Serialize {
+    /// The type returned by TdLib for this function.
+    type Output;
+
+    /// The name of the function in the TdLib API, which is also the
+    /// `@type` of the request.
+    const TYPE: &'static str;
+
+    /// Whether sending the request again doesn't have any additional effect,
+    /// so that it can be retried safely. It's true for the functions only
+    /// getting or searching data.
+    const IDEMPOTENT: bool = false;
+
+    /// Convert the raw JSON response of TdLib, which isn't an error, to the
+    /// output of the function.
+    #[doc(hidden)]
+    fn parse_response(response: &str) -> Result<Self::Output, Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::ChatList;
+    use crate::functions::{AnswerCallbackQuery, GetChats};
+    use serde_json::json;
+
+    #[test]
+    fn serialize_request() {
+        let request = GetChats {
+            chat_list: None,
+            limit: 20,
+        };
+        assert_eq!(GetChats::TYPE, "getChats");
+        assert_eq!(
+            serde_json::to_value(request).unwrap(),
+            json!({"@type": "getChats", "chat_list": null, "limit": 20})
+        );
+    }
+
+    #[test]
+    fn build_request() {
+        let request = GetChats::new(20);
+        assert_eq!(request.chat_list, None);
+        assert_eq!(request.limit, 20);
+
+        let request = request.chat_list(ChatList::Archive);
+        assert_eq!(request.chat_list, Some(ChatList::Archive));
+    }
+
+    #[test]
+    fn serialize_request_with_int64() {
+        let request = AnswerCallbackQuery {
+            callback_query_id: 123,
+            text: String::new(),
+            show_alert: false,
+            url: String::new(),
+            cache_time: 0,
+        };
+        assert_eq!(
+            serde_json::to_value(request).unwrap()["callback_query_id"],
+            "123"
+        );
+    }
+}
@@ -6,66 +6,230 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+pub mod auth;
+mod backend;
+#[cfg(feature = "bot")]
+pub mod bot;
+pub mod chat_list;
+mod client;
+pub mod download;
+mod error;
+pub mod formatting;
+mod function;
 mod generated;
+pub mod logging;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 mod observer;
+pub mod pagination;
+pub mod replay;
+mod retry;
+mod runtime;
+#[cfg(feature = "state")]
+pub mod state;
 mod tdjson;
+#[cfg(test)]
+mod test_util;
+mod timer;
+mod updates;
+pub mod upload;
 
-pub use generated::{enums, functions, types};
+pub use backend::{Backend, Tdjson};
+pub use client::{Client, ClientBuilder};
+#[cfg(feature = "dlopen")]
+pub use error::LoadError;
+pub use error::{Error, ErrorKind};
+pub use function::Function;
+pub use generated::{dispatch_update, enums, functions, types, UpdateHandler};
+pub use retry::RetryPolicy;
+pub use timer::timeout;
+pub use updates::{OverflowPolicy, Updates};
 
 use enums::Update;
-use once_cell::sync::Lazy;
-use serde_json::Value;
-use std::sync::atomic::{AtomicU32, Ordering};
-
-static EXTRA_COUNTER: AtomicU32 = AtomicU32::new(0);
-static OBSERVER: Lazy<observer::Observer> = Lazy::new(observer::Observer::new);
+use runtime::Runtime;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::borrow::Cow;
 
 /// Create a TdLib client returning its id. Note that to start receiving
 /// updates for a client you need to send at least a request with it first.
 pub fn create_client() -> i32 {
-    tdjson::create_client()
+    Runtime::default_runtime().backend().create_client()
 }
 
 /// Receive a single update or response from TdLib. If it's an update, it
 /// returns a tuple with the `Update` and the associated `client_id`.
 /// Note that to start receiving updates for a client you need to send
 /// at least a request with it first.
+///
+/// This must not be used together with `Client`, which already receives
+/// updates and responses from a background thread.
 pub fn receive() -> Option<(Update, i32)> {
-    let response = tdjson::receive(2.0);
-    if let Some(response_str) = response {
-        let response: Value = serde_json::from_str(&response_str).unwrap();
+    Runtime::default_runtime().receive()
+}
 
-        match response.get("@extra") {
-            Some(_) => {
-                OBSERVER.notify(response);
-            }
-            None => {
-                let client_id = response["@client_id"].as_i64().unwrap() as i32;
-                match serde_json::from_value(response) {
-                    Ok(update) => {
-                        return Some((update, client_id));
-                    }
-                    Err(e) => {
-                        log::warn!(
-                            "Received an unknown response: {}\nReason: {}",
-                            response_str,
-                            e
-                        );
-                    }
-                }
+/// Load TdLib from the path in the `TDJSON_PATH` environment variable or,
+/// if it's not set, from the default search path of the system, returning
+/// its version.
+///
+/// TdLib is loaded automatically the first time it's used, panicking if it
+/// can't be loaded, so call this first to handle the error instead. If the
+/// library is already loaded, this just returns its version.
+#[cfg(feature = "dlopen")]
+pub fn load_tdjson() -> Result<&'static str, LoadError> {
+    tdjson::load(None)
+}
+
+/// Like `load_tdjson`, but loads TdLib from the given path.
+#[cfg(feature = "dlopen")]
+pub fn load_tdjson_from<P: AsRef<std::path::Path>>(path: P) -> Result<&'static str, LoadError> {
+    tdjson::load(Some(path.as_ref()))
+}
+
+pub(crate) async fn send_function<F: Function>(
+    client_id: i32,
+    function: F,
+) -> Result<F::Output, Error> {
+    Runtime::default_runtime().send(client_id, function).await
+}
+
+pub(crate) fn execute_function<F: Function>(function: F) -> Result<F::Output, Error> {
+    Runtime::default_runtime().execute(function)
+}
+
+/// The fields used to route the objects received from TdLib, deserialized
+/// without the rest of the object.
+#[derive(Deserialize)]
+pub(crate) struct Header<'a> {
+    #[serde(rename = "@type", borrow)]
+    pub ty: Cow<'a, str>,
+    #[serde(rename = "@extra", default)]
+    pub extra: Option<u32>,
+    #[serde(rename = "@client_id", default)]
+    pub client_id: Option<i32>,
+}
+
+impl<'a> Header<'a> {
+    pub fn peek(json: &'a str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|source| Error::Deserialize {
+            json: json.to_owned(),
+            source,
+        })
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.ty == "error"
+    }
+}
+
+/// Turn a response of TdLib into an error, if TdLib answered with one.
+pub(crate) fn check_response(response: String, is_error: bool) -> Result<String, Error> {
+    if is_error {
+        return Err(parse_response::<types::Error>(&response)?.into());
+    }
+    Ok(response)
+}
+
+/// Deserialize a successful response of TdLib to the expected type.
+pub(crate) fn parse_response<T: DeserializeOwned>(response: &str) -> Result<T, Error> {
+    serde_json::from_str(response).map_err(|source| Error::Deserialize {
+        json: response.to_owned(),
+        source,
+    })
+}
+
+/// Like `parse_response`, but for functions returning `Ok`, whose content
+/// doesn't matter.
+pub(crate) fn parse_ok_response(_response: &str) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_error_response() {
+        let response = r#"{"@type":"error","code":400,"message":"Bad Request","@extra":3}"#;
+        let header = Header::peek(response).unwrap();
+        assert_eq!(header.extra, Some(3));
+        match check_response(response.to_owned(), header.is_error()) {
+            Err(Error::Tdlib(error)) => {
+                assert_eq!(error.code, 400);
+                assert_eq!(error.message, "Bad Request");
             }
+            other => panic!("unexpected result: {:?}", other),
         }
     }
 
-    None
-}
+    // Malformed objects are unrecognized rather than errors with the
+    // `unknown-variants` feature, see `parse_unknown_update`
+    #[cfg(not(feature = "unknown-variants"))]
+    #[test]
+    fn parse_malformed_response_keeps_json() {
+        let response = json!({"@type": "user", "id": "not a number"}).to_string();
+        match parse_response::<enums::User>(&response) {
+            Err(Error::Deserialize { json, .. }) => assert_eq!(json, response),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "unknown-variants")]
+    #[test]
+    fn parse_unknown_update() {
+        let update = json!({"@type": "updateFromTheFuture", "chat_id": 1});
+        assert_eq!(
+            parse_response::<Update>(&update.to_string()).unwrap(),
+            Update::Unrecognized(update)
+        );
 
-pub(crate) async fn send_request(client_id: i32, mut request: Value) -> Value {
-    let extra = EXTRA_COUNTER.fetch_add(1, Ordering::Relaxed);
-    request["@extra"] = serde_json::to_value(extra).unwrap();
+        let update = json!({"@type": "updateOption", "name": "version", "value": {"@type": "optionValueEmpty"}});
+        assert!(matches!(
+            parse_response::<Update>(&update.to_string()).unwrap(),
+            Update::Option(_)
+        ));
 
-    let receiver = OBSERVER.subscribe(extra);
-    tdjson::send(client_id, request.to_string());
+        // A known type failing to deserialize is an error, not an unknown type
+        let user = json!({"@type": "user", "id": "not a number"});
+        assert!(matches!(
+            parse_response::<enums::User>(&user.to_string()),
+            Err(Error::Deserialize { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn dispatch_to_combined_handlers() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl UpdateHandler for Recorder {
+            async fn on_option(&self, update: &types::UpdateOption) {
+                self.0.lock().unwrap().push(update.name.clone());
+            }
+        }
+
+        let first = Recorder::default();
+        let second = Recorder::default();
+        let update = Update::Option(types::UpdateOption {
+            name: "version".into(),
+            value: enums::OptionValue::Empty,
+        });
+        dispatch_update(&(&first, &second), &update).await;
+        let other = Update::ConnectionState(types::UpdateConnectionState {
+            state: enums::ConnectionState::Ready,
+        });
+        dispatch_update(&first, &other).await;
 
-    receiver.await.unwrap()
+        assert_eq!(*first.0.lock().unwrap(), vec!["version".to_owned()]);
+        assert_eq!(*second.0.lock().unwrap(), vec!["version".to_owned()]);
+    }
+
+    #[test]
+    fn parse_ok_response_ignores_content() {
+        assert!(parse_ok_response(r#"{"@type":"ok"}"#).is_ok());
+        assert!(parse_ok_response(r#"{"@type":"ok","unexpected":[1]}"#).is_ok());
+    }
 }
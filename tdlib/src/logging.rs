@@ -0,0 +1,182 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Forward the internal log messages of TdLib to the `log` crate.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use tdlib::logging::LogBridge;
+//!
+//! LogBridge::new()
+//!     .on_fatal(|message| eprintln!("TdLib is about to abort: {}", message))
+//!     .install();
+//! ```
+
+use crate::tdjson;
+use log::Level;
+use once_cell::sync::Lazy;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::RwLock;
+
+/// The target of the log records of TdLib.
+pub const TARGET: &str = "tdlib";
+
+type FatalHook = Box<dyn Fn(&str) + Send + Sync>;
+
+static FATAL_HOOK: Lazy<RwLock<Option<FatalHook>>> = Lazy::new(RwLock::default);
+
+/// A builder to configure the bridge between the log messages of TdLib and
+/// the `log` crate.
+///
+/// Once installed, the messages are logged with the `tdlib` target and a
+/// level depending on their TdLib verbosity level: fatal errors (0) and
+/// errors (1) as `Level::Error`, warnings (2) as `Level::Warn`, information
+/// (3) as `Level::Info`, debug messages (4) as `Level::Debug` and everything
+/// more verbose as `Level::Trace`.
+///
+/// TdLib still writes its messages to the log stream configured with
+/// `setLogStream`, so it may be useful to set it to `logStreamEmpty`. The
+/// verbosity of TdLib itself is set with `setLogVerbosityLevel`.
+pub struct LogBridge {
+    max_verbosity_level: i32,
+    fatal_hook: Option<FatalHook>,
+}
+
+impl Default for LogBridge {
+    fn default() -> Self {
+        LogBridge {
+            max_verbosity_level: level_to_verbosity(log::max_level().to_level()),
+            fatal_hook: None,
+        }
+    }
+}
+
+impl LogBridge {
+    /// Create a bridge forwarding the messages allowed by the current
+    /// maximum level of the `log` crate.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum TdLib verbosity level of the messages to forward.
+    pub fn max_verbosity_level(mut self, max_verbosity_level: i32) -> Self {
+        self.max_verbosity_level = max_verbosity_level;
+        self
+    }
+
+    /// Set a function called with the fatal errors of TdLib. TdLib aborts
+    /// the process right after a fatal error, so this is the last chance to
+    /// save its message somewhere. The hook must not call TdLib.
+    pub fn on_fatal<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.fatal_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Register the bridge with TdLib, replacing any previously installed
+    /// bridge.
+    pub fn install(self) {
+        *FATAL_HOOK.write().unwrap() = self.fatal_hook;
+        tdjson::set_log_message_callback(self.max_verbosity_level, Some(log_message));
+    }
+}
+
+/// Stop forwarding the log messages of TdLib.
+pub fn uninstall() {
+    tdjson::set_log_message_callback(0, None);
+    *FATAL_HOOK.write().unwrap() = None;
+}
+
+fn verbosity_to_level(verbosity_level: i32) -> Level {
+    match verbosity_level {
+        i32::MIN..=1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+fn level_to_verbosity(level: Option<Level>) -> i32 {
+    match level {
+        None => 0,
+        Some(Level::Error) => 1,
+        Some(Level::Warn) => 2,
+        Some(Level::Info) => 3,
+        Some(Level::Debug) => 4,
+        Some(Level::Trace) => 1024,
+    }
+}
+
+fn handle_message(verbosity_level: i32, message: &str) {
+    if verbosity_level == 0 {
+        if let Some(hook) = &*FATAL_HOOK.read().unwrap() {
+            hook(message);
+        }
+    }
+    log::log!(target: TARGET, verbosity_to_level(verbosity_level), "{}", message);
+}
+
+extern "C" fn log_message(verbosity_level: c_int, message: *const c_char) {
+    if message.is_null() {
+        return;
+    }
+
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    let message = message.trim_end();
+
+    // Unwinding into TdLib is undefined behavior
+    if panic::catch_unwind(AssertUnwindSafe(|| {
+        handle_message(verbosity_level, message)
+    }))
+    .is_err()
+    {
+        eprintln!("Failed to handle a TdLib log message: {}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn map_verbosity_levels() {
+        assert_eq!(verbosity_to_level(0), Level::Error);
+        assert_eq!(verbosity_to_level(1), Level::Error);
+        assert_eq!(verbosity_to_level(2), Level::Warn);
+        assert_eq!(verbosity_to_level(3), Level::Info);
+        assert_eq!(verbosity_to_level(4), Level::Debug);
+        assert_eq!(verbosity_to_level(5), Level::Trace);
+        assert_eq!(level_to_verbosity(Some(Level::Warn)), 2);
+        assert_eq!(level_to_verbosity(None), 0);
+    }
+
+    #[test]
+    fn fatal_messages_reach_the_hook() {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let hook_messages = messages.clone();
+        *FATAL_HOOK.write().unwrap() = Some(Box::new(move |message: &str| {
+            hook_messages.lock().unwrap().push(message.to_owned())
+        }));
+
+        let fatal = CString::new("Fatal error\n").unwrap();
+        let warning = CString::new("Warning").unwrap();
+        log_message(2, warning.as_ptr());
+        log_message(0, fatal.as_ptr());
+
+        *FATAL_HOOK.write().unwrap() = None;
+        assert_eq!(*messages.lock().unwrap(), vec!["Fatal error".to_owned()]);
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A scriptable `Backend` to test application code without TdLib. Requires
+//! the `mock` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! # async fn example() -> Result<(), tdlib::Error> {
+//! use serde_json::json;
+//! use std::sync::Arc;
+//! use tdlib::mock::MockBackend;
+//! use tdlib::Client;
+//!
+//! let mock = Arc::new(MockBackend::new());
+//! mock.respond("getOption", json!({"@type": "optionValueString", "value": "1.8.19"}));
+//!
+//! let client = Client::builder().backend(mock.clone()).build();
+//! let version = client.get_option("version".into()).await?;
+//!
+//! assert_eq!(mock.requests_of_type("getOption").len(), 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::enums::Update;
+use crate::Backend;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+type Responder = Arc<dyn Fn(&Value) -> Value + Send + Sync>;
+
+#[derive(Default)]
+struct MockState {
+    last_client_id: i32,
+    responders: HashMap<String, Responder>,
+    incoming: VecDeque<String>,
+    requests: Vec<(i32, Value)>,
+}
+
+/// A `Backend` answering requests with canned responses registered by their
+/// `@type`, which also records the requests sent to it and lets tests inject
+/// arbitrary updates.
+///
+/// Requests without a registered response are answered with an error with
+/// code 404, unless they were sent without `@extra` (like the `close`
+/// request sent when a `Client` is dropped), in which case they're ignored.
+#[derive(Default)]
+pub struct MockBackend {
+    state: Mutex<MockState>,
+    condvar: Condvar,
+}
+
+impl MockBackend {
+    /// Create a mock backend without any registered response.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer every request of the given `@type` with `response`, which must
+    /// serialize to a TdLib object (for example one of the `enums`).
+    pub fn respond(&self, request_type: &str, response: impl Serialize) {
+        let response = serde_json::to_value(response).expect("the response must be serializable");
+        self.respond_with(request_type, move |_| response.clone());
+    }
+
+    /// Answer every request of the given `@type` with the result of calling
+    /// `responder` with the request. The responder may use the mock itself,
+    /// for example to push the updates caused by the request.
+    pub fn respond_with<F>(&self, request_type: &str, responder: F)
+    where
+        F: Fn(&Value) -> Value + Send + Sync + 'static,
+    {
+        self.state
+            .lock()
+            .unwrap()
+            .responders
+            .insert(request_type.into(), Arc::new(responder));
+    }
+
+    /// Deliver an update to the client with the given id.
+    pub fn push_update(&self, client_id: i32, update: Update) {
+        let mut update = serde_json::to_value(update).expect("updates are always serializable");
+        update["@client_id"] = Value::from(client_id);
+        self.push_incoming(update);
+    }
+
+    /// Every request sent so far, together with the id of the client that
+    /// sent it.
+    pub fn requests(&self) -> Vec<(i32, Value)> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The requests of the given `@type` sent so far.
+    pub fn requests_of_type(&self, request_type: &str) -> Vec<Value> {
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .filter(|(_, request)| request["@type"] == request_type)
+            .map(|(_, request)| request.clone())
+            .collect()
+    }
+
+    /// Forget the requests sent so far.
+    pub fn clear_requests(&self) {
+        self.state.lock().unwrap().requests.clear();
+    }
+
+    fn push_incoming(&self, value: Value) {
+        self.state
+            .lock()
+            .unwrap()
+            .incoming
+            .push_back(value.to_string());
+        self.condvar.notify_one();
+    }
+
+    fn response_to(&self, request: &Value) -> Value {
+        let request_type = request["@type"].as_str().unwrap_or_default();
+        let responder = self
+            .state
+            .lock()
+            .unwrap()
+            .responders
+            .get(request_type)
+            .cloned();
+        match responder {
+            Some(responder) => responder(request),
+            None => serde_json::json!({
+                "@type": "error",
+                "code": 404,
+                "message": format!("No mock response for {}", request_type),
+            }),
+        }
+    }
+}
+
+impl Backend for MockBackend {
+    fn create_client(&self) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        state.last_client_id += 1;
+        state.last_client_id
+    }
+
+    fn send(&self, client_id: i32, request: String) {
+        let request: Value = serde_json::from_str(&request).expect("requests are valid JSON");
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .push((client_id, request.clone()));
+
+        let extra = match request.get("@extra") {
+            Some(extra) => extra.clone(),
+            None => return,
+        };
+
+        let mut response = self.response_to(&request);
+        response["@extra"] = extra;
+        response["@client_id"] = Value::from(client_id);
+        self.push_incoming(response);
+    }
+
+    fn receive(&self, timeout: f64) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        let (mut state, _) = self
+            .condvar
+            .wait_timeout_while(state, Duration::from_secs_f64(timeout), |state| {
+                state.incoming.is_empty()
+            })
+            .unwrap();
+        state.incoming.pop_front()
+    }
+
+    fn execute(&self, request: String) -> Option<String> {
+        let request: Value = serde_json::from_str(&request).ok()?;
+        Some(self.response_to(&request).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::{AuthorizationState, OptionValue};
+    use crate::functions::GetOption;
+    use crate::types::{OptionValueString, UpdateAuthorizationState};
+    use crate::{Client, Error};
+    use futures_util::StreamExt;
+    use serde_json::json;
+
+    fn version() -> OptionValue {
+        OptionValue::String(OptionValueString {
+            value: "1.8.19".into(),
+        })
+    }
+
+    #[tokio::test]
+    async fn canned_response() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond("getOption", version());
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let response = client.get_option("version".into()).await.unwrap();
+        assert_eq!(response, version());
+
+        let requests = mock.requests_of_type("getOption");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["name"], "version");
+    }
+
+    #[tokio::test]
+    async fn missing_response_is_an_error() {
+        let mock = Arc::new(MockBackend::new());
+        let client = Client::builder().backend(mock.clone()).build();
+        match client.get_me().await {
+            Err(Error::Tdlib(error)) => assert_eq!(error.code, 404),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn injected_updates() {
+        let mock = Arc::new(MockBackend::new());
+        let client = Client::builder().backend(mock.clone()).build();
+        let mut updates = client.updates();
+
+        let update = Update::AuthorizationState(UpdateAuthorizationState {
+            authorization_state: AuthorizationState::WaitPhoneNumber,
+        });
+        mock.push_update(client.id(), update.clone());
+
+        assert_eq!(updates.next().await, Some(update));
+        assert_eq!(
+            client.authorization_state(),
+            Some(AuthorizationState::WaitPhoneNumber)
+        );
+    }
+
+    #[tokio::test]
+    async fn closed_client_ends_updates() {
+        let mock = Arc::new(MockBackend::new());
+        let client = Client::builder().backend(mock.clone()).build();
+        let mut updates = client.updates();
+
+        mock.push_update(
+            client.id(),
+            Update::AuthorizationState(UpdateAuthorizationState {
+                authorization_state: AuthorizationState::Closed,
+            }),
+        );
+
+        assert!(updates.next().await.is_some());
+        assert_eq!(updates.next().await, None);
+        assert!(client.is_closed());
+        assert!(matches!(client.get_me().await, Err(Error::Closed)));
+    }
+
+    #[test]
+    fn execute_request() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond_with(
+            "getOption",
+            |request| json!({"@type": "optionValueString", "value": request["name"]}),
+        );
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let response = client.execute(GetOption::new("version".into())).unwrap();
+        assert_eq!(
+            response,
+            OptionValue::String(OptionValueString {
+                value: "version".into()
+            })
+        );
+    }
+}
@@ -5,13 +5,18 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+use crate::Error;
 use futures_channel::oneshot;
-use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::RwLock;
 
+struct PendingRequest {
+    client_id: i32,
+    sender: oneshot::Sender<Result<String, Error>>,
+}
+
 pub(super) struct Observer {
-    requests: RwLock<HashMap<u32, oneshot::Sender<Value>>>,
+    requests: RwLock<HashMap<u32, PendingRequest>>,
 }
 
 impl Observer {
@@ -21,23 +26,90 @@ impl Observer {
         }
     }
 
-    pub fn subscribe(&self, extra: u32) -> oneshot::Receiver<Value> {
+    pub fn subscribe(
+        &self,
+        client_id: i32,
+        extra: u32,
+    ) -> oneshot::Receiver<Result<String, Error>> {
         let (sender, receiver) = oneshot::channel();
-        self.requests.write().unwrap().insert(extra, sender);
+        self.requests
+            .write()
+            .unwrap()
+            .insert(extra, PendingRequest { client_id, sender });
         receiver
     }
 
-    pub fn notify(&self, response: Value) {
-        let extra = response["@extra"].as_u64().unwrap() as u32;
+    /// Forget about a request, so that its eventual response is discarded.
+    pub fn unsubscribe(&self, extra: u32) {
+        self.requests.write().unwrap().remove(&extra);
+    }
+
+    /// Resolve the request with the given `@extra` with its raw response, or
+    /// the error TdLib answered with.
+    pub fn notify(&self, extra: u32, response: Result<String, Error>) {
         match self.requests.write().unwrap().remove(&extra) {
-            Some(sender) => {
-                if sender.send(response).is_err() {
+            Some(request) => {
+                if request.sender.send(response).is_err() {
                     log::warn!("Got a response of an unaccessible request");
                 }
             }
             None => {
-                log::warn!("Got a response of an unknown request");
+                log::debug!("Got a response of an unknown or cancelled request");
+            }
+        }
+    }
+
+    /// Fail all the pending requests of a client with `Error::Closed`.
+    pub fn close_client(&self, client_id: i32) {
+        let mut requests = self.requests.write().unwrap();
+        let extras = requests
+            .iter()
+            .filter(|(_, request)| request.client_id == client_id)
+            .map(|(extra, _)| *extra)
+            .collect::<Vec<_>>();
+
+        for extra in extras {
+            if let Some(request) = requests.remove(&extra) {
+                let _ = request.sender.send(Err(Error::Closed));
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    #[test]
+    fn notify_resolves_request() {
+        let observer = Observer::new();
+        let receiver = observer.subscribe(1, 7);
+        observer.notify(7, Ok(r#"{"@type":"ok","@extra":7}"#.to_owned()));
+        let response = receiver.now_or_never().unwrap().unwrap().unwrap();
+        assert_eq!(response, r#"{"@type":"ok","@extra":7}"#);
+    }
+
+    #[test]
+    fn unsubscribed_request_is_cancelled() {
+        let observer = Observer::new();
+        let receiver = observer.subscribe(1, 7);
+        observer.unsubscribe(7);
+        assert!(observer.requests.read().unwrap().is_empty());
+        assert!(receiver.now_or_never().unwrap().is_err());
+    }
+
+    #[test]
+    fn close_client_fails_its_requests() {
+        let observer = Observer::new();
+        let closed = observer.subscribe(1, 7);
+        let open = observer.subscribe(2, 8);
+        observer.close_client(1);
+        assert!(matches!(
+            closed.now_or_never(),
+            Some(Ok(Err(Error::Closed)))
+        ));
+        assert!(observer.requests.read().unwrap().contains_key(&8));
+        drop(open);
+    }
+}
@@ -0,0 +1,503 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Streams going through all the results of the functions returning them a
+//! page at a time, like `getChatHistory` or `searchMessages`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use tdlib::functions::GetChatHistory;
+//!
+//! # async fn example(client: tdlib::Client) -> Result<(), tdlib::Error> {
+//! let request = GetChatHistory {
+//!     chat_id: 777,
+//!     from_message_id: 0,
+//!     offset: 0,
+//!     limit: 100,
+//!     only_local: false,
+//! };
+//! let mut messages = client.paginate(request).take(500);
+//! while let Some(message) = messages.next().await {
+//!     println!("{}", message?.id);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{enums, functions, types, Client, Error, Function};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A function whose results are split into pages, each request giving the
+/// cursor of the next one.
+pub trait Paginated: Function<Output: Send> + Clone + Send + 'static {
+    /// A single result.
+    type Item;
+
+    /// Split the response to this request into its results and the request
+    /// of the next page, which is `None` after the last page. The request is
+    /// a continuation if it was returned by this method, rather than the one
+    /// the pagination started from.
+    fn next_page(
+        self,
+        output: Self::Output,
+        is_continuation: bool,
+    ) -> Result<(Vec<Self::Item>, Option<Self>), Error>;
+}
+
+/// The `from_message_id` and `offset` of a page of a message history.
+type Cursor = (i64, i32);
+
+/// Turn a page of messages into its results and the cursor of the next
+/// page, if any.
+///
+/// The histories include the message with the identifier they start from,
+/// so the messages not older than the cursor of a continuation are skipped,
+/// having been returned by the previous page. The first page is kept whole,
+/// with the starting message of the caller and the newer messages of a
+/// negative offset. TdLib may return fewer messages than asked, so only an
+/// empty page ends the history. A continuation returning no older message
+/// is asked again once with an offset of -1, and the history ends if that
+/// one doesn't return any either, since TdLib keeps returning the message
+/// the page starts from at the end of the history.
+fn message_history(
+    messages: enums::Messages,
+    from_message_id: i64,
+    offset: i32,
+    is_continuation: bool,
+) -> Result<(Vec<types::Message>, Option<Cursor>), Error> {
+    let messages = match messages {
+        enums::Messages::Messages(messages) => messages.messages,
+        #[cfg(feature = "unknown-variants")]
+        enums::Messages::Unrecognized(json) => return Err(Error::unrecognized(&json)),
+    };
+    let messages = messages.into_iter().flatten().collect::<Vec<_>>();
+    if messages.is_empty() {
+        return Ok((messages, None));
+    }
+
+    let messages = messages
+        .into_iter()
+        .filter(|message| !is_continuation || message.id < from_message_id)
+        .collect::<Vec<_>>();
+    let next = match messages.last() {
+        Some(message) => Some((message.id, 0)),
+        None if offset == 0 => Some((from_message_id, -1)),
+        None => None,
+    };
+    Ok((messages, next))
+}
+
+impl Paginated for functions::GetChatHistory {
+    type Item = types::Message;
+
+    fn next_page(
+        self,
+        output: Self::Output,
+        is_continuation: bool,
+    ) -> Result<(Vec<Self::Item>, Option<Self>), Error> {
+        let (messages, next) =
+            message_history(output, self.from_message_id, self.offset, is_continuation)?;
+        let next = next.map(|(from_message_id, offset)| functions::GetChatHistory {
+            from_message_id,
+            offset,
+            ..self
+        });
+        Ok((messages, next))
+    }
+}
+
+impl Paginated for functions::GetMessageThreadHistory {
+    type Item = types::Message;
+
+    fn next_page(
+        self,
+        output: Self::Output,
+        is_continuation: bool,
+    ) -> Result<(Vec<Self::Item>, Option<Self>), Error> {
+        let (messages, next) =
+            message_history(output, self.from_message_id, self.offset, is_continuation)?;
+        let next = next.map(
+            |(from_message_id, offset)| functions::GetMessageThreadHistory {
+                from_message_id,
+                offset,
+                ..self
+            },
+        );
+        Ok((messages, next))
+    }
+}
+
+impl Paginated for functions::SearchChatMessages {
+    type Item = types::Message;
+
+    fn next_page(
+        self,
+        output: Self::Output,
+        _: bool,
+    ) -> Result<(Vec<Self::Item>, Option<Self>), Error> {
+        let (messages, from_message_id) = match output {
+            enums::FoundChatMessages::FoundChatMessages(found) => {
+                (found.messages, found.next_from_message_id)
+            }
+            #[cfg(feature = "unknown-variants")]
+            enums::FoundChatMessages::Unrecognized(json) => return Err(Error::unrecognized(&json)),
+        };
+        let next = (from_message_id != 0).then_some(functions::SearchChatMessages {
+            from_message_id,
+            offset: 0,
+            ..self
+        });
+        Ok((messages, next))
+    }
+}
+
+/// Turn a page of found messages into its results and the offset of the
+/// next page, which is empty after the last one.
+fn found_messages(
+    output: enums::FoundMessages,
+) -> Result<(Vec<types::Message>, Option<String>), Error> {
+    let (messages, next_offset) = match output {
+        enums::FoundMessages::FoundMessages(found) => (found.messages, found.next_offset),
+        #[cfg(feature = "unknown-variants")]
+        enums::FoundMessages::Unrecognized(json) => return Err(Error::unrecognized(&json)),
+    };
+    let next = (!next_offset.is_empty()).then_some(next_offset);
+    Ok((messages, next))
+}
+
+impl Paginated for functions::SearchMessages {
+    type Item = types::Message;
+
+    fn next_page(
+        self,
+        output: Self::Output,
+        _: bool,
+    ) -> Result<(Vec<Self::Item>, Option<Self>), Error> {
+        let (messages, next) = found_messages(output)?;
+        let next = next.map(|offset| functions::SearchMessages { offset, ..self });
+        Ok((messages, next))
+    }
+}
+
+impl Paginated for functions::SearchSecretMessages {
+    type Item = types::Message;
+
+    fn next_page(
+        self,
+        output: Self::Output,
+        _: bool,
+    ) -> Result<(Vec<Self::Item>, Option<Self>), Error> {
+        let (messages, next) = found_messages(output)?;
+        let next = next.map(|offset| functions::SearchSecretMessages { offset, ..self });
+        Ok((messages, next))
+    }
+}
+
+impl Paginated for functions::SearchCallMessages {
+    type Item = types::Message;
+
+    fn next_page(
+        self,
+        output: Self::Output,
+        _: bool,
+    ) -> Result<(Vec<Self::Item>, Option<Self>), Error> {
+        let (messages, next) = found_messages(output)?;
+        let next = next.map(|offset| functions::SearchCallMessages { offset, ..self });
+        Ok((messages, next))
+    }
+}
+
+impl Paginated for functions::GetSupergroupMembers {
+    type Item = types::ChatMember;
+
+    fn next_page(
+        self,
+        output: Self::Output,
+        _: bool,
+    ) -> Result<(Vec<Self::Item>, Option<Self>), Error> {
+        let (members, total_count) = match output {
+            enums::ChatMembers::ChatMembers(members) => (members.members, members.total_count),
+            #[cfg(feature = "unknown-variants")]
+            enums::ChatMembers::Unrecognized(json) => return Err(Error::unrecognized(&json)),
+        };
+        let offset = self.offset + members.len() as i32;
+        let next = (!members.is_empty() && offset < total_count)
+            .then_some(functions::GetSupergroupMembers { offset, ..self });
+        Ok((members, next))
+    }
+}
+
+type PageFuture<'a, F> =
+    Pin<Box<dyn Future<Output = Result<<F as Function>::Output, Error>> + Send + 'a>>;
+
+/// A stream of the results of a `Paginated` function, returned by
+/// `Client::paginate`.
+///
+/// The pages are requested lazily, when the results of the previous one
+/// have been consumed. The stream ends after the last page, or after
+/// yielding the error of a failed request or of a response of an unknown
+/// type.
+pub struct Paginator<'a, F: Paginated> {
+    client: &'a Client,
+    request: Option<F>,
+    next: Option<F>,
+    is_continuation: bool,
+    items: VecDeque<F::Item>,
+    pending: Option<PageFuture<'a, F>>,
+}
+
+// The pending future is boxed, and nothing else is ever pinned
+impl<F: Paginated> Unpin for Paginator<'_, F> {}
+
+impl<'a, F: Paginated> Paginator<'a, F> {
+    pub(crate) fn new(client: &'a Client, request: F) -> Self {
+        Paginator {
+            client,
+            request: None,
+            next: Some(request),
+            is_continuation: false,
+            items: VecDeque::new(),
+            pending: None,
+        }
+    }
+}
+
+impl<F: Paginated> Stream for Paginator<'_, F> {
+    type Item = Result<F::Item, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(item) = this.items.pop_front() {
+                return Poll::Ready(Some(Ok(item)));
+            }
+
+            let pending = match &mut this.pending {
+                Some(pending) => pending,
+                None => {
+                    let request = match this.next.take() {
+                        Some(request) => request,
+                        None => return Poll::Ready(None),
+                    };
+                    this.request = Some(request.clone());
+                    this.pending.insert(Box::pin(this.client.send(request)))
+                }
+            };
+
+            let output = match pending.as_mut().poll(cx) {
+                Poll::Ready(output) => output,
+                Poll::Pending => return Poll::Pending,
+            };
+            this.pending = None;
+
+            let request = this.request.take().unwrap();
+            let page = output.and_then(|output| request.next_page(output, this.is_continuation));
+            match page {
+                Ok((items, next)) => {
+                    this.is_continuation = true;
+                    this.items.extend(items);
+                    this.next = next;
+                }
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBackend;
+    use crate::test_util;
+    use futures_util::StreamExt;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    fn message(id: i64) -> Value {
+        serde_json::to_value(test_util::message(id)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn chat_history_until_empty_page() {
+        let mock = Arc::new(MockBackend::new());
+        // TdLib includes the message the page starts from, and may return
+        // short pages before the end
+        mock.respond_with("getChatHistory", |request| {
+            let ids: &[i64] = match request["from_message_id"].as_i64().unwrap() {
+                0 => &[50],
+                50 => &[50, 40, 30],
+                30 => &[30, 20],
+                _ => &[20],
+            };
+            let messages = ids.iter().map(|id| message(*id)).collect::<Vec<_>>();
+            json!({"@type": "messages", "total_count": 4, "messages": messages})
+        });
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let request = functions::GetChatHistory {
+            chat_id: 7,
+            from_message_id: 0,
+            offset: 0,
+            limit: 3,
+            only_local: false,
+        };
+        let ids = client
+            .paginate(request)
+            .map(|message| message.unwrap().id)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ids, vec![50, 40, 30, 20]);
+        // The last message is asked again with an offset of -1 before ending
+        let requests = mock.requests_of_type("getChatHistory");
+        assert_eq!(requests.len(), 5);
+        assert_eq!(requests[4]["from_message_id"], 20);
+        assert_eq!(requests[4]["offset"], -1);
+    }
+
+    #[tokio::test]
+    async fn chat_history_after_a_page_of_the_cursor() {
+        let mock = Arc::new(MockBackend::new());
+        // A continuation may only return the message it starts from, even if
+        // there are older messages
+        mock.respond_with("getChatHistory", |request| {
+            let from_message_id = request["from_message_id"].as_i64().unwrap();
+            let ids: &[i64] = match (from_message_id, request["offset"].as_i64().unwrap()) {
+                (0, 0) => &[50],
+                (50, 0) => &[50],
+                (50, -1) => &[60, 50, 40],
+                _ => &[],
+            };
+            let messages = ids.iter().map(|id| message(*id)).collect::<Vec<_>>();
+            json!({"@type": "messages", "total_count": 3, "messages": messages})
+        });
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let request = functions::GetChatHistory {
+            chat_id: 7,
+            from_message_id: 0,
+            offset: 0,
+            limit: 3,
+            only_local: false,
+        };
+        let ids = client
+            .paginate(request)
+            .map(|message| message.unwrap().id)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ids, vec![50, 40]);
+        assert_eq!(mock.requests_of_type("getChatHistory").len(), 4);
+    }
+
+    #[cfg(feature = "unknown-variants")]
+    #[tokio::test]
+    async fn unknown_pages_are_errors() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond(
+            "getSupergroupMembers",
+            json!({"@type": "chatMembersFromTheFuture"}),
+        );
+        let client = Client::builder().backend(mock.clone()).build();
+        let request = functions::GetSupergroupMembers {
+            supergroup_id: 1,
+            filter: None,
+            offset: 0,
+            limit: 200,
+        };
+        let results = client.paginate(request).collect::<Vec<_>>().await;
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(Error::Deserialize { .. })));
+    }
+
+    #[tokio::test]
+    async fn chat_history_around_a_message() {
+        let mock = Arc::new(MockBackend::new());
+        // A negative offset returns the newer messages before the one the
+        // history starts from
+        mock.respond_with("getChatHistory", |request| {
+            let from_message_id = request["from_message_id"].as_i64().unwrap();
+            let ids: &[i64] = match (from_message_id, request["offset"].as_i64().unwrap()) {
+                (40, -2) => &[60, 50, 40, 30],
+                (30, 0) => &[30, 20],
+                _ => &[20],
+            };
+            let messages = ids.iter().map(|id| message(*id)).collect::<Vec<_>>();
+            json!({"@type": "messages", "total_count": 5, "messages": messages})
+        });
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let request = functions::GetChatHistory {
+            chat_id: 7,
+            from_message_id: 40,
+            offset: -2,
+            limit: 4,
+            only_local: false,
+        };
+        let ids = client
+            .paginate(request)
+            .map(|message| message.unwrap().id)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ids, vec![60, 50, 40, 30, 20]);
+    }
+
+    #[tokio::test]
+    async fn search_until_empty_offset() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond_with("searchMessages", |request| {
+            let (ids, next_offset): (&[i64], _) = match request["offset"].as_str().unwrap() {
+                "" => (&[3, 2], "next"),
+                _ => (&[1], ""),
+            };
+            let messages = ids.iter().map(|id| message(*id)).collect::<Vec<_>>();
+            json!({
+                "@type": "foundMessages",
+                "total_count": 3,
+                "messages": messages,
+                "next_offset": next_offset
+            })
+        });
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let request = functions::SearchMessages {
+            chat_list: None,
+            query: "query".into(),
+            offset: String::new(),
+            limit: 2,
+            filter: None,
+            min_date: 0,
+            max_date: 0,
+        };
+        let ids = client
+            .paginate(request)
+            .map(|message| message.unwrap().id)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ids, vec![3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn errors_end_the_stream() {
+        let mock = Arc::new(MockBackend::new());
+        let client = Client::builder().backend(mock.clone()).build();
+        let request = functions::GetSupergroupMembers {
+            supergroup_id: 1,
+            filter: None,
+            offset: 0,
+            limit: 200,
+        };
+        let results = client.paginate(request).collect::<Vec<_>>().await;
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+    }
+}
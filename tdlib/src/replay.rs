@@ -0,0 +1,482 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Record the traffic between the library and TdLib to a JSON-lines file and
+//! replay it later, without network access.
+//!
+//! A session can be captured by wrapping the real backend:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use tdlib::replay::RecordingBackend;
+//! use tdlib::{Client, Tdjson};
+//!
+//! let backend = RecordingBackend::create(Tdjson, "session.jsonl").unwrap();
+//! let client = Client::builder().backend(Arc::new(backend)).build();
+//! ```
+//!
+//! and then reproduced with the same application code:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use tdlib::replay::ReplayBackend;
+//! use tdlib::Client;
+//!
+//! let backend = ReplayBackend::open("session.jsonl").unwrap();
+//! let client = Client::builder().backend(Arc::new(backend)).build();
+//! ```
+
+use crate::Backend;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// A line of a recording. `time` is the number of milliseconds elapsed since
+/// the start of the recording.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A client was created.
+    CreateClient { time: u64, client_id: i32 },
+    /// A request was sent.
+    Send {
+        time: u64,
+        client_id: i32,
+        request: Value,
+    },
+    /// A response or an update was received.
+    Receive {
+        time: u64,
+        client_id: Option<i32>,
+        response: Value,
+    },
+    /// A request was executed synchronously.
+    Execute {
+        time: u64,
+        request: Value,
+        response: Option<Value>,
+    },
+}
+
+/// A `Backend` forwarding everything to another backend, while writing every
+/// event to a JSON-lines recording which can be replayed with
+/// `ReplayBackend`.
+///
+/// Each line is flushed as soon as it's written, so the recording is usable
+/// even if the application crashes. Note that the recording contains
+/// everything exchanged with TdLib, including phone numbers, authentication
+/// codes and messages.
+pub struct RecordingBackend<B: Backend> {
+    inner: B,
+    writer: Mutex<Box<dyn Write + Send>>,
+    start: Instant,
+}
+
+impl<B: Backend> RecordingBackend<B> {
+    /// Record the events of `inner` to `writer`.
+    pub fn new<W: Write + Send + 'static>(inner: B, writer: W) -> Self {
+        RecordingBackend {
+            inner,
+            writer: Mutex::new(Box::new(writer)),
+            start: Instant::now(),
+        }
+    }
+
+    /// Record the events of `inner` to a new file at `path`, truncating it if
+    /// it already exists.
+    pub fn create<P: AsRef<Path>>(inner: B, path: P) -> io::Result<Self> {
+        Ok(Self::new(inner, File::create(path)?))
+    }
+
+    /// The wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn elapsed(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn record(&self, event: Event) {
+        let mut line = serde_json::to_string(&event).expect("events are always serializable");
+        line.push('\n');
+
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = writer
+            .write_all(line.as_bytes())
+            .and_then(|_| writer.flush())
+        {
+            log::warn!("Failed to record an event: {}", e);
+        }
+    }
+}
+
+fn parse_json(json: &str) -> Option<Value> {
+    match serde_json::from_str(json) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log::warn!("Not recording invalid JSON: {}\nReason: {}", json, e);
+            None
+        }
+    }
+}
+
+impl<B: Backend> Backend for RecordingBackend<B> {
+    fn create_client(&self) -> i32 {
+        let client_id = self.inner.create_client();
+        self.record(Event::CreateClient {
+            time: self.elapsed(),
+            client_id,
+        });
+        client_id
+    }
+
+    fn send(&self, client_id: i32, request: String) {
+        // Record the request before sending it, so that it always comes
+        // before its response in the recording
+        if let Some(value) = parse_json(&request) {
+            self.record(Event::Send {
+                time: self.elapsed(),
+                client_id,
+                request: value,
+            });
+        }
+        self.inner.send(client_id, request);
+    }
+
+    fn receive(&self, timeout: f64) -> Option<String> {
+        let response = self.inner.receive(timeout)?;
+        if let Some(value) = parse_json(&response) {
+            self.record(Event::Receive {
+                time: self.elapsed(),
+                client_id: value["@client_id"].as_i64().map(|id| id as i32),
+                response: value,
+            });
+        }
+        Some(response)
+    }
+
+    fn execute(&self, request: String) -> Option<String> {
+        let time = self.elapsed();
+        let response = self.inner.execute(request.clone());
+        if let Some(value) = parse_json(&request) {
+            self.record(Event::Execute {
+                time,
+                request: value,
+                response: response.as_deref().and_then(parse_json),
+            });
+        }
+        response
+    }
+}
+
+#[derive(Default)]
+struct ReplayState {
+    events: VecDeque<Event>,
+    executions: Vec<(Value, Option<Value>)>,
+    extras: HashMap<String, Value>,
+    divergences: Vec<String>,
+}
+
+/// A `Backend` feeding a recording made with `RecordingBackend` back to the
+/// library.
+///
+/// The recorded responses and updates are delivered in their original order,
+/// as fast as possible. Each of them is only delivered once every request
+/// recorded before it has been sent again, so that the application sees the
+/// same sequence of events as in the original session. Since the `@extra`
+/// of the requests changes between sessions, the responses are matched to
+/// the new requests automatically.
+///
+/// A request which doesn't match any recorded request is a divergence from
+/// the original session: it's logged and can be inspected with
+/// `divergences`, but it's otherwise ignored.
+#[derive(Default)]
+pub struct ReplayBackend {
+    state: Mutex<ReplayState>,
+    condvar: Condvar,
+}
+
+impl ReplayBackend {
+    /// Replay the given events.
+    pub fn new<I: IntoIterator<Item = Event>>(events: I) -> Self {
+        let mut state = ReplayState::default();
+        for event in events {
+            match event {
+                Event::Execute {
+                    request, response, ..
+                } => state.executions.push((request, response)),
+                event => state.events.push_back(event),
+            }
+        }
+
+        ReplayBackend {
+            state: Mutex::new(state),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Replay a recording read from `reader`.
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut events = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            events.push(serde_json::from_str(&line)?);
+        }
+        Ok(Self::new(events))
+    }
+
+    /// Replay the recording at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Whether every recorded client, request, response and update has been
+    /// replayed.
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().unwrap().events.is_empty()
+    }
+
+    /// The descriptions of the requests which didn't match the recording.
+    pub fn divergences(&self) -> Vec<String> {
+        self.state.lock().unwrap().divergences.clone()
+    }
+
+    fn diverge(state: &mut ReplayState, description: String) {
+        log::warn!("The replay diverged from the recording: {}", description);
+        state.divergences.push(description);
+    }
+}
+
+/// Remove the `@extra` field from a request, returning it.
+fn take_extra(request: &mut Value) -> Option<Value> {
+    request.as_object_mut()?.remove("@extra")
+}
+
+impl Backend for ReplayBackend {
+    fn create_client(&self) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        let position = state
+            .events
+            .iter()
+            .position(|event| matches!(event, Event::CreateClient { .. }));
+
+        let client_id = match position.and_then(|i| state.events.remove(i)) {
+            Some(Event::CreateClient { client_id, .. }) => client_id,
+            _ => {
+                Self::diverge(&mut state, "unexpected client creation".into());
+                0
+            }
+        };
+
+        self.condvar.notify_all();
+        client_id
+    }
+
+    fn send(&self, client_id: i32, request: String) {
+        let mut request: Value = match serde_json::from_str(&request) {
+            Ok(request) => request,
+            Err(e) => {
+                log::warn!("Sent an invalid request: {}\nReason: {}", request, e);
+                return;
+            }
+        };
+        let extra = take_extra(&mut request);
+
+        let mut state = self.state.lock().unwrap();
+        let position = state.events.iter().position(|event| match event {
+            Event::Send {
+                client_id: recorded_client_id,
+                request: recorded,
+                ..
+            } => {
+                let mut recorded = recorded.clone();
+                take_extra(&mut recorded);
+                *recorded_client_id == client_id && recorded == request
+            }
+            _ => false,
+        });
+
+        match position.and_then(|i| state.events.remove(i)) {
+            Some(Event::Send {
+                request: mut recorded,
+                ..
+            }) => {
+                if let (Some(recorded), Some(extra)) = (take_extra(&mut recorded), extra) {
+                    state.extras.insert(recorded.to_string(), extra);
+                }
+            }
+            _ => Self::diverge(
+                &mut state,
+                format!("unexpected request from client {}: {}", client_id, request),
+            ),
+        }
+
+        self.condvar.notify_all();
+    }
+
+    fn receive(&self, timeout: f64) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        let (mut state, _) = self
+            .condvar
+            .wait_timeout_while(state, Duration::from_secs_f64(timeout), |state| {
+                !matches!(state.events.front(), Some(Event::Receive { .. }))
+            })
+            .unwrap();
+
+        match state.events.pop_front() {
+            Some(Event::Receive { mut response, .. }) => {
+                if let Some(recorded) = take_extra(&mut response) {
+                    let extra = state.extras.remove(&recorded.to_string());
+                    response["@extra"] = extra.unwrap_or(recorded);
+                }
+                Some(response.to_string())
+            }
+            Some(event) => {
+                state.events.push_front(event);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn execute(&self, request: String) -> Option<String> {
+        let mut request: Value = serde_json::from_str(&request).ok()?;
+        let extra = take_extra(&mut request);
+
+        let mut state = self.state.lock().unwrap();
+        let position = state.executions.iter().position(|(recorded, _)| {
+            let mut recorded = recorded.clone();
+            take_extra(&mut recorded);
+            recorded == request
+        });
+
+        match position {
+            Some(i) => {
+                let (_, response) = state.executions.remove(i);
+                let mut response = response?;
+                if let Some(extra) = extra {
+                    response["@extra"] = extra;
+                }
+                Some(response.to_string())
+            }
+            None => {
+                Self::diverge(&mut state, format!("unexpected execution: {}", request));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::{AuthorizationState, OptionValue, Update};
+    use crate::functions::GetOption;
+    use crate::mock::MockBackend;
+    use crate::types::{OptionValueString, UpdateAuthorizationState};
+    use crate::Client;
+    use futures_util::StreamExt;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn version() -> OptionValue {
+        OptionValue::String(OptionValueString {
+            value: "1.8.19".into(),
+        })
+    }
+
+    fn wait_phone_number() -> Update {
+        Update::AuthorizationState(UpdateAuthorizationState {
+            authorization_state: AuthorizationState::WaitPhoneNumber,
+        })
+    }
+
+    async fn session(backend: Arc<dyn Backend>, inject: impl FnOnce(i32)) {
+        let client = Client::builder().backend(backend).build();
+        let mut updates = client.updates();
+
+        let response = client.get_option("version".into()).await.unwrap();
+        assert_eq!(response, version());
+
+        inject(client.id());
+        assert_eq!(updates.next().await, Some(wait_phone_number()));
+
+        let response = client.execute(GetOption::new("version".into())).unwrap();
+        assert_eq!(response, version());
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let mock = Arc::new(MockBackend::new());
+        mock.respond("getOption", version());
+
+        let buffer = SharedBuffer::default();
+        let recorder = Arc::new(RecordingBackend::new(mock.clone(), buffer.clone()));
+        session(recorder, |client_id| {
+            mock.push_update(client_id, wait_phone_number())
+        })
+        .await;
+
+        let recording = buffer.0.lock().unwrap().clone();
+        let replay = Arc::new(ReplayBackend::from_reader(&recording[..]).unwrap());
+        session(replay.clone(), |_| {}).await;
+
+        assert!(replay.divergences().is_empty());
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn responses_wait_for_their_request() {
+        let replay = ReplayBackend::new(vec![
+            Event::Send {
+                time: 0,
+                client_id: 1,
+                request: serde_json::json!({"@type": "getMe", "@extra": 5}),
+            },
+            Event::Receive {
+                time: 1,
+                client_id: Some(1),
+                response: serde_json::json!({"@type": "ok", "@extra": 5}),
+            },
+        ]);
+        assert_eq!(replay.receive(0.0), None);
+
+        replay.send(1, r#"{"@type":"getMe","@extra":42}"#.into());
+        let response: Value = serde_json::from_str(&replay.receive(0.0).unwrap()).unwrap();
+        assert_eq!(response["@extra"], 42);
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn unexpected_request_diverges() {
+        let replay = ReplayBackend::new(Vec::new());
+        replay.send(1, r#"{"@type":"getMe"}"#.into());
+        assert_eq!(replay.divergences().len(), 1);
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::error::ErrorKind;
+use crate::timer::Delay;
+use crate::Error;
+use std::future::Future;
+use std::time::Duration;
+
+/// When to automatically send again the requests failing because of
+/// transient errors, like rate limits or network issues. It only applies to
+/// idempotent requests (see `Function::IDEMPOTENT`).
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use tdlib::{Client, RetryPolicy};
+///
+/// let client = Client::builder()
+///     .retry_policy(RetryPolicy::new().max_delay(Duration::from_secs(10)))
+///     .build();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    max_delay: Duration,
+    default_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            max_delay: Duration::from_secs(60),
+            default_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy retrying a request at most 3 times, waiting at most 60
+    /// seconds before each retry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of times a request is retried.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the maximum time to wait before retrying a request. If TdLib asks
+    /// to wait longer than this, the error is returned immediately.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the time to wait before retrying a request, when TdLib doesn't
+    /// specify it. Defaults to 1 second.
+    pub fn default_delay(mut self, default_delay: Duration) -> Self {
+        self.default_delay = default_delay;
+        self
+    }
+
+    /// The time to wait before retrying after the given error, if it should
+    /// be retried at all.
+    fn delay(&self, error: &Error) -> Option<Duration> {
+        let kind = error.kind();
+        if !kind.is_transient() {
+            return None;
+        }
+
+        let delay = match kind {
+            ErrorKind::RateLimited {
+                retry_after: Some(retry_after),
+            } => retry_after,
+            _ => self.default_delay,
+        };
+        (delay <= self.max_delay).then_some(delay)
+    }
+
+    /// Run the request until it succeeds, fails with an error which can't be
+    /// retried or runs out of retries.
+    pub(crate) async fn run<T, F, Fut>(&self, mut request: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut retries = 0;
+        loop {
+            let error = match request().await {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+
+            match self.delay(&error) {
+                Some(delay) if retries < self.max_retries => {
+                    log::debug!("Retrying a request in {:?}: {}", delay, error);
+                    retries += 1;
+                    Delay::new(delay).await;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBackend;
+    use crate::types;
+    use crate::Client;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    fn flood_wait(seconds: u64) -> Error {
+        Error::Tdlib(types::Error {
+            code: 429,
+            message: format!("Too Many Requests: retry after {}", seconds),
+        })
+    }
+
+    #[test]
+    fn delays() {
+        let policy = RetryPolicy::new().max_delay(Duration::from_secs(10));
+        assert_eq!(policy.delay(&flood_wait(5)), Some(Duration::from_secs(5)));
+        assert_eq!(policy.delay(&flood_wait(30)), None);
+        assert_eq!(policy.delay(&Error::Timeout), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(&Error::Closed), None);
+    }
+
+    /// Answer `request_type` with a rate limit error the first `failures`
+    /// times and with `response` after that, returning the number of
+    /// requests received.
+    fn flaky(
+        mock: &MockBackend,
+        request_type: &str,
+        failures: u32,
+        response: Value,
+    ) -> Arc<AtomicU32> {
+        let count = Arc::new(AtomicU32::new(0));
+        let count_clone = count.clone();
+        mock.respond_with(request_type, move |_| {
+            if count_clone.fetch_add(1, Ordering::SeqCst) < failures {
+                json!({"@type": "error", "code": 429, "message": "Too Many Requests: retry after 0"})
+            } else {
+                response.clone()
+            }
+        });
+        count
+    }
+
+    #[tokio::test]
+    async fn retry_idempotent_requests() {
+        let mock = Arc::new(MockBackend::new());
+        let count = flaky(&mock, "getOption", 2, json!({"@type": "optionValueEmpty"}));
+
+        let client = Client::builder()
+            .backend(mock.clone())
+            .retry_policy(RetryPolicy::new())
+            .build();
+        client.get_option("version".into()).await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn other_requests_are_not_retried() {
+        let mock = Arc::new(MockBackend::new());
+        let count = flaky(&mock, "setOption", 1, json!({"@type": "ok"}));
+
+        let client = Client::builder()
+            .backend(mock.clone())
+            .retry_policy(RetryPolicy::new())
+            .build();
+        let result = client.set_option("name".into(), None).await;
+        assert!(matches!(
+            result.map_err(|e| e.kind()),
+            Err(ErrorKind::RateLimited { .. })
+        ));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The state shared by all the clients using the same `Backend`: the pending
+//! requests, the registered clients and the thread receiving from TdLib.
+
+use crate::backend::{Backend, Tdjson};
+use crate::client::ClientState;
+use crate::enums::{AuthorizationState, Update};
+use crate::observer::Observer;
+use crate::{Error, Function, Header};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Once, RwLock, Weak};
+use std::thread;
+
+static EXTRA_COUNTER: AtomicU32 = AtomicU32::new(0);
+static DEFAULT_RUNTIME: Lazy<Arc<Runtime>> = Lazy::new(|| Arc::new(Runtime::new(Arc::new(Tdjson))));
+static RUNTIMES: Lazy<Mutex<HashMap<usize, Weak<Runtime>>>> = Lazy::new(Mutex::default);
+
+pub(crate) struct Runtime {
+    backend: Arc<dyn Backend>,
+    observer: Observer,
+    clients: RwLock<HashMap<i32, Arc<ClientState>>>,
+    receiver: Once,
+}
+
+impl Runtime {
+    fn new(backend: Arc<dyn Backend>) -> Self {
+        Runtime {
+            backend,
+            observer: Observer::new(),
+            clients: RwLock::default(),
+            receiver: Once::new(),
+        }
+    }
+
+    /// The runtime using the real TdLib.
+    pub fn default_runtime() -> &'static Arc<Runtime> {
+        &DEFAULT_RUNTIME
+    }
+
+    /// The runtime using the given backend, shared by all its clients.
+    pub fn for_backend(backend: Arc<dyn Backend>) -> Arc<Runtime> {
+        let key = Arc::as_ptr(&backend) as *const () as usize;
+        let mut runtimes = RUNTIMES.lock().unwrap();
+        runtimes.retain(|_, runtime| runtime.strong_count() > 0);
+
+        if let Some(runtime) = runtimes.get(&key).and_then(Weak::upgrade) {
+            return runtime;
+        }
+
+        let runtime = Arc::new(Runtime::new(backend));
+        runtimes.insert(key, Arc::downgrade(&runtime));
+        runtime
+    }
+
+    pub fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
+
+    /// Spawn the thread receiving updates and responses for the clients of
+    /// this runtime, if it isn't running already. The thread stops once the
+    /// runtime is dropped.
+    pub fn start_receiver(self: &Arc<Self>) {
+        self.receiver.call_once(|| {
+            let runtime = Arc::downgrade(self);
+            thread::Builder::new()
+                .name("tdlib-receiver".into())
+                .spawn(move || {
+                    while let Some(runtime) = runtime.upgrade() {
+                        runtime.receive();
+                    }
+                })
+                .expect("failed to spawn the receiver thread");
+        });
+    }
+
+    pub fn register_client(&self, client_id: i32, state: Arc<ClientState>) {
+        self.clients.write().unwrap().insert(client_id, state);
+    }
+
+    pub fn unregister_client(&self, client_id: i32) {
+        self.clients.write().unwrap().remove(&client_id);
+    }
+
+    /// Receive a single update or response from the backend, forwarding it
+    /// to the right request or client.
+    pub fn receive(&self) -> Option<(Update, i32)> {
+        let response = self.backend.receive(2.0)?;
+
+        // Only the routing fields are read at first, the response is then
+        // deserialized once to its final type
+        let (extra, client_id, is_error) = match Header::peek(&response) {
+            Ok(header) => (header.extra, header.client_id, header.is_error()),
+            Err(e) => {
+                log::warn!("Received an invalid response: {}", e);
+                return None;
+            }
+        };
+
+        if let Some(extra) = extra {
+            self.observer
+                .notify(extra, crate::check_response(response, is_error));
+            return None;
+        }
+
+        let client_id = match client_id {
+            Some(client_id) => client_id,
+            None => {
+                log::warn!("Received an update without client id: {}", response);
+                return None;
+            }
+        };
+
+        match serde_json::from_str(&response) {
+            Ok(update) => {
+                self.dispatch_update(client_id, &update);
+                Some((update, client_id))
+            }
+            Err(e) => {
+                log::warn!("Received an unknown response: {}\nReason: {}", response, e);
+                None
+            }
+        }
+    }
+
+    fn dispatch_update(&self, client_id: i32, update: &Update) {
+        if let Update::AuthorizationState(update) = update {
+            if let AuthorizationState::Closed = update.authorization_state {
+                self.observer.close_client(client_id);
+            }
+        }
+
+        let state = self.clients.read().unwrap().get(&client_id).cloned();
+        if let Some(state) = state {
+            state.dispatch(update);
+        }
+    }
+
+    pub async fn send<F: Function>(&self, client_id: i32, function: F) -> Result<F::Output, Error> {
+        let request = serde_json::to_string(&function).expect("requests are always serializable");
+        F::parse_response(&self.send_request(client_id, &request).await?)
+    }
+
+    /// Send a request serialized as a JSON object, returning the raw
+    /// response, or the error TdLib answered with.
+    pub async fn send_request(&self, client_id: i32, request: &str) -> Result<String, Error> {
+        let extra = EXTRA_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+        // Make sure the pending request gets removed from the observer when
+        // this future is dropped before receiving a response
+        struct Guard<'a>(&'a Observer, u32);
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                self.0.unsubscribe(self.1);
+            }
+        }
+
+        let receiver = self.observer.subscribe(client_id, extra);
+        let _guard = Guard(&self.observer, extra);
+        self.backend.send(client_id, with_extra(request, extra));
+
+        receiver.await.map_err(|_| Error::Cancelled)?
+    }
+
+    pub fn execute<F: Function>(&self, function: F) -> Result<F::Output, Error> {
+        let request = serde_json::to_string(&function).expect("requests are always serializable");
+        let response = self.backend.execute(request).ok_or(Error::Cancelled)?;
+        let is_error = Header::peek(&response)?.is_error();
+        F::parse_response(&crate::check_response(response, is_error)?)
+    }
+}
+
+/// Add `@extra` to a request serialized as a JSON object, without parsing it
+/// again.
+fn with_extra(request: &str, extra: u32) -> String {
+    debug_assert!(request.starts_with('{') && request.len() > 2);
+    format!("{{\"@extra\":{},{}", extra, &request[1..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn add_extra_to_request() {
+        let request = with_extra(r#"{"@type":"getOption","name":"version"}"#, 42);
+        let request: Value = serde_json::from_str(&request).unwrap();
+        assert_eq!(
+            request,
+            json!({"@type": "getOption", "name": "version", "@extra": 42})
+        );
+    }
+}
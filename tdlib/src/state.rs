@@ -0,0 +1,500 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A local copy of the data TdLib expects clients to keep from the updates:
+//! users, chats, groups, chat lists and options.
+//!
+//! TdLib sends the full objects once, and then only the fields that changed,
+//! so the state must be fed every update of a client, starting from its
+//! creation.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use tdlib::enums::ChatList;
+//! use tdlib::state::State;
+//! use tdlib::Client;
+//!
+//! # async fn example(client: Client) {
+//! let state = State::new();
+//! let mut updates = client.updates();
+//! while let Some(update) = updates.next().await {
+//!     state.apply(&update);
+//!     for chat_id in state.chat_list(&ChatList::Main).iter().take(10) {
+//!         println!("{}", state.chat(*chat_id).unwrap().title);
+//!     }
+//! }
+//! # }
+//! ```
+
+use crate::chat_list::Chats;
+use crate::enums::{ChatList, OptionValue, Update};
+use crate::types::{BasicGroup, Chat, ChatPosition, SecretChat, Supergroup, User};
+use crate::updates::{OverflowPolicy, Subscription};
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+
+const DEFAULT_CHANGES_BUFFER: usize = 1024;
+
+/// A part of the state which changed after applying an update.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// The user with this identifier was added or changed.
+    User(i64),
+    /// The basic group with this identifier was added or changed.
+    BasicGroup(i64),
+    /// The supergroup with this identifier was added or changed.
+    Supergroup(i64),
+    /// The secret chat with this identifier was added or changed.
+    SecretChat(i32),
+    /// The chat with this identifier was added or changed.
+    Chat(i64),
+    /// The chats of this list, or their order, changed.
+    ChatList(ChatList),
+    /// The option with this name was set or removed.
+    Option(String),
+}
+
+#[derive(Default)]
+struct Data {
+    users: HashMap<i64, User>,
+    basic_groups: HashMap<i64, BasicGroup>,
+    supergroups: HashMap<i64, Supergroup>,
+    secret_chats: HashMap<i32, SecretChat>,
+    chats: HashMap<i64, Chat>,
+    // `ChatList` isn't hashable, and there are only a few lists anyway
+    chat_lists: Vec<(ChatList, Chats)>,
+    options: HashMap<String, OptionValue>,
+}
+
+impl Data {
+    fn chat_list_mut(&mut self, list: &ChatList) -> &mut Chats {
+        let index = match self.chat_lists.iter().position(|(l, _)| l == list) {
+            Some(index) => index,
+            None => {
+                self.chat_lists.push((list.clone(), Chats::default()));
+                self.chat_lists.len() - 1
+            }
+        };
+        &mut self.chat_lists[index].1
+    }
+
+    /// Move a chat to its new position in a chat list, removing it from the
+    /// list if the order is 0.
+    fn set_position(&mut self, chat_id: i64, position: &ChatPosition, changes: &mut Vec<Change>) {
+        let chat = match self.chats.get_mut(&chat_id) {
+            Some(chat) => chat,
+            None => return,
+        };
+
+        chat.positions.retain(|p| p.list != position.list);
+        if position.order != 0 {
+            chat.positions.push(position.clone());
+        }
+
+        self.chat_list_mut(&position.list)
+            .set_order(chat_id, position.order);
+        changes.push(Change::ChatList(position.list.clone()));
+    }
+
+    /// Change a field of a chat, if it's known.
+    fn update_chat(&mut self, chat_id: i64, update: impl FnOnce(&mut Chat)) -> Option<Change> {
+        let chat = self.chats.get_mut(&chat_id)?;
+        update(chat);
+        Some(Change::Chat(chat_id))
+    }
+
+    fn apply(&mut self, update: &Update, changes: &mut Vec<Change>) {
+        let change = match update {
+            Update::User(update) => {
+                let user = &update.user;
+                self.users.insert(user.id, user.clone());
+                Some(Change::User(user.id))
+            }
+            Update::UserStatus(update) => self.users.get_mut(&update.user_id).map(|user| {
+                user.status = update.status.clone();
+                Change::User(update.user_id)
+            }),
+            Update::BasicGroup(update) => {
+                let group = &update.basic_group;
+                self.basic_groups.insert(group.id, group.clone());
+                Some(Change::BasicGroup(group.id))
+            }
+            Update::Supergroup(update) => {
+                let group = &update.supergroup;
+                self.supergroups.insert(group.id, group.clone());
+                Some(Change::Supergroup(group.id))
+            }
+            Update::SecretChat(update) => {
+                let chat = &update.secret_chat;
+                self.secret_chats.insert(chat.id, chat.clone());
+                Some(Change::SecretChat(chat.id))
+            }
+            Update::NewChat(update) => {
+                let mut chat = update.chat.clone();
+                let positions = std::mem::take(&mut chat.positions);
+                let chat_id = chat.id;
+                self.chats.insert(chat_id, chat);
+                changes.push(Change::Chat(chat_id));
+                for position in &positions {
+                    self.set_position(chat_id, position, changes);
+                }
+                None
+            }
+            Update::ChatPosition(update) => {
+                self.set_position(update.chat_id, &update.position, changes);
+                None
+            }
+            Update::ChatLastMessage(update) => {
+                changes.extend(self.update_chat(update.chat_id, |chat| {
+                    chat.last_message = update.last_message.clone();
+                }));
+                for position in &update.positions {
+                    self.set_position(update.chat_id, position, changes);
+                }
+                None
+            }
+            Update::ChatDraftMessage(update) => {
+                changes.extend(self.update_chat(update.chat_id, |chat| {
+                    chat.draft_message = update.draft_message.clone();
+                }));
+                for position in &update.positions {
+                    self.set_position(update.chat_id, position, changes);
+                }
+                None
+            }
+            Update::ChatTitle(update) => self.update_chat(update.chat_id, |chat| {
+                chat.title = update.title.clone();
+            }),
+            Update::ChatPhoto(update) => self.update_chat(update.chat_id, |chat| {
+                chat.photo = update.photo.clone();
+            }),
+            Update::ChatPermissions(update) => self.update_chat(update.chat_id, |chat| {
+                chat.permissions = update.permissions.clone();
+            }),
+            Update::ChatReadInbox(update) => self.update_chat(update.chat_id, |chat| {
+                chat.last_read_inbox_message_id = update.last_read_inbox_message_id;
+                chat.unread_count = update.unread_count;
+            }),
+            Update::ChatReadOutbox(update) => self.update_chat(update.chat_id, |chat| {
+                chat.last_read_outbox_message_id = update.last_read_outbox_message_id;
+            }),
+            Update::ChatActionBar(update) => self.update_chat(update.chat_id, |chat| {
+                chat.action_bar = update.action_bar.clone();
+            }),
+            Update::ChatAvailableReactions(update) => self.update_chat(update.chat_id, |chat| {
+                chat.available_reactions = update.available_reactions.clone();
+            }),
+            Update::ChatMessageSender(update) => self.update_chat(update.chat_id, |chat| {
+                chat.message_sender_id = update.message_sender_id.clone();
+            }),
+            Update::ChatMessageAutoDeleteTime(update) => self.update_chat(update.chat_id, |chat| {
+                chat.message_auto_delete_time = update.message_auto_delete_time;
+            }),
+            Update::ChatNotificationSettings(update) => self.update_chat(update.chat_id, |chat| {
+                chat.notification_settings = update.notification_settings.clone();
+            }),
+            Update::ChatPendingJoinRequests(update) => self.update_chat(update.chat_id, |chat| {
+                chat.pending_join_requests = update.pending_join_requests.clone();
+            }),
+            Update::ChatReplyMarkup(update) => self.update_chat(update.chat_id, |chat| {
+                chat.reply_markup_message_id = update.reply_markup_message_id;
+            }),
+            Update::ChatBackground(update) => self.update_chat(update.chat_id, |chat| {
+                chat.background = update.background.clone();
+            }),
+            Update::ChatTheme(update) => self.update_chat(update.chat_id, |chat| {
+                chat.theme_name = update.theme_name.clone();
+            }),
+            Update::ChatUnreadMentionCount(update) => self.update_chat(update.chat_id, |chat| {
+                chat.unread_mention_count = update.unread_mention_count;
+            }),
+            Update::ChatUnreadReactionCount(update) => self.update_chat(update.chat_id, |chat| {
+                chat.unread_reaction_count = update.unread_reaction_count;
+            }),
+            Update::ChatVideoChat(update) => self.update_chat(update.chat_id, |chat| {
+                chat.video_chat = update.video_chat.clone();
+            }),
+            Update::ChatDefaultDisableNotification(update) => {
+                self.update_chat(update.chat_id, |chat| {
+                    chat.default_disable_notification = update.default_disable_notification;
+                })
+            }
+            Update::ChatHasProtectedContent(update) => self.update_chat(update.chat_id, |chat| {
+                chat.has_protected_content = update.has_protected_content;
+            }),
+            Update::ChatIsTranslatable(update) => self.update_chat(update.chat_id, |chat| {
+                chat.is_translatable = update.is_translatable;
+            }),
+            Update::ChatIsMarkedAsUnread(update) => self.update_chat(update.chat_id, |chat| {
+                chat.is_marked_as_unread = update.is_marked_as_unread;
+            }),
+            Update::ChatBlockList(update) => self.update_chat(update.chat_id, |chat| {
+                chat.block_list = update.block_list.clone();
+            }),
+            Update::ChatHasScheduledMessages(update) => self.update_chat(update.chat_id, |chat| {
+                chat.has_scheduled_messages = update.has_scheduled_messages;
+            }),
+            Update::Option(update) => {
+                if let OptionValue::Empty = update.value {
+                    self.options.remove(&update.name);
+                } else {
+                    self.options
+                        .insert(update.name.clone(), update.value.clone());
+                }
+                Some(Change::Option(update.name.clone()))
+            }
+            _ => None,
+        };
+
+        changes.extend(change);
+    }
+}
+
+/// The local state of a client, built from its updates and safe to share
+/// between threads.
+///
+/// The getters return copies of the current objects, and every change is
+/// notified to the streams returned by `State::changes`.
+#[derive(Default)]
+pub struct State {
+    data: RwLock<Data>,
+    subscriptions: Mutex<Vec<Arc<Subscription<Change>>>>,
+}
+
+impl State {
+    /// Create an empty state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update the state with an update of TdLib, ignoring the updates which
+    /// don't affect it.
+    pub fn apply(&self, update: &Update) {
+        let mut changes = Vec::new();
+        self.data.write().unwrap().apply(update, &mut changes);
+        if changes.is_empty() {
+            return;
+        }
+
+        self.subscriptions.lock().unwrap().retain(|subscription| {
+            changes
+                .iter()
+                .all(|change| subscription.push(change.clone()))
+        });
+    }
+
+    /// Get a stream of the changes of the state made from now on. Up to 1024
+    /// changes are buffered, the oldest ones being dropped after that.
+    pub fn changes(&self) -> Changes {
+        let subscription = Arc::new(Subscription::new(
+            DEFAULT_CHANGES_BUFFER,
+            OverflowPolicy::DropOldest,
+        ));
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .push(subscription.clone());
+        Changes { subscription }
+    }
+
+    /// Get a user by its identifier.
+    pub fn user(&self, user_id: i64) -> Option<User> {
+        self.data.read().unwrap().users.get(&user_id).cloned()
+    }
+
+    /// Get a basic group by its identifier.
+    pub fn basic_group(&self, basic_group_id: i64) -> Option<BasicGroup> {
+        let data = self.data.read().unwrap();
+        data.basic_groups.get(&basic_group_id).cloned()
+    }
+
+    /// Get a supergroup or a channel by its identifier.
+    pub fn supergroup(&self, supergroup_id: i64) -> Option<Supergroup> {
+        let data = self.data.read().unwrap();
+        data.supergroups.get(&supergroup_id).cloned()
+    }
+
+    /// Get a secret chat by its identifier.
+    pub fn secret_chat(&self, secret_chat_id: i32) -> Option<SecretChat> {
+        let data = self.data.read().unwrap();
+        data.secret_chats.get(&secret_chat_id).cloned()
+    }
+
+    /// Get a chat by its identifier.
+    pub fn chat(&self, chat_id: i64) -> Option<Chat> {
+        self.data.read().unwrap().chats.get(&chat_id).cloned()
+    }
+
+    /// Get the identifiers of the chats of a list known so far, in the order
+    /// they should be displayed. More chats are loaded with `loadChats`.
+    pub fn chat_list(&self, list: &ChatList) -> Vec<i64> {
+        let data = self.data.read().unwrap();
+        data.chat_lists
+            .iter()
+            .find(|(l, _)| l == list)
+            .map(|(_, chats)| chats.chat_ids().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the value of an option, if it's set.
+    pub fn option(&self, name: &str) -> Option<OptionValue> {
+        self.data.read().unwrap().options.get(name).cloned()
+    }
+}
+
+/// A stream of the changes of a `State`, returned by `State::changes`.
+pub struct Changes {
+    subscription: Arc<Subscription<Change>>,
+}
+
+impl Stream for Changes {
+    type Item = Change;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.subscription.poll_next(cx)
+    }
+}
+
+impl Drop for Changes {
+    fn drop(&mut self) {
+        self.subscription.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enums, types};
+    use futures_util::{FutureExt, StreamExt};
+
+    fn new_chat(id: i64, title: &str) -> Update {
+        Update::NewChat(types::UpdateNewChat {
+            chat: types::Chat {
+                id,
+                r#type: enums::ChatType::Private(types::ChatTypePrivate { user_id: id }),
+                title: title.into(),
+                photo: None,
+                permissions: Default::default(),
+                last_message: None,
+                positions: Vec::new(),
+                message_sender_id: None,
+                block_list: None,
+                has_protected_content: false,
+                is_translatable: false,
+                is_marked_as_unread: false,
+                has_scheduled_messages: false,
+                can_be_deleted_only_for_self: false,
+                can_be_deleted_for_all_users: false,
+                can_be_reported: false,
+                default_disable_notification: false,
+                unread_count: 0,
+                last_read_inbox_message_id: 0,
+                last_read_outbox_message_id: 0,
+                unread_mention_count: 0,
+                unread_reaction_count: 0,
+                notification_settings: Default::default(),
+                available_reactions: enums::ChatAvailableReactions::All,
+                message_auto_delete_time: 0,
+                background: None,
+                theme_name: String::new(),
+                action_bar: None,
+                video_chat: Default::default(),
+                pending_join_requests: None,
+                reply_markup_message_id: 0,
+                draft_message: None,
+                client_data: String::new(),
+            },
+        })
+    }
+
+    fn position(chat_id: i64, order: i64) -> Update {
+        Update::ChatPosition(types::UpdateChatPosition {
+            chat_id,
+            position: ChatPosition {
+                list: ChatList::Main,
+                order,
+                is_pinned: false,
+                source: None,
+            },
+        })
+    }
+
+    #[test]
+    fn chat_lists_follow_positions() {
+        let state = State::new();
+        for (id, title) in [(1, "a"), (2, "b"), (3, "c")] {
+            state.apply(&new_chat(id, title));
+        }
+        state.apply(&position(1, 10));
+        state.apply(&position(2, 30));
+        state.apply(&position(3, 20));
+        assert_eq!(state.chat_list(&ChatList::Main), vec![2, 3, 1]);
+
+        state.apply(&position(1, 40));
+        state.apply(&position(2, 0));
+        assert_eq!(state.chat_list(&ChatList::Main), vec![1, 3]);
+        assert_eq!(state.chat(2).unwrap().positions, vec![]);
+        assert!(state.chat_list(&ChatList::Archive).is_empty());
+    }
+
+    #[test]
+    fn changes_are_notified() {
+        let state = State::new();
+        let mut changes = state.changes();
+        state.apply(&new_chat(1, "a"));
+        state.apply(&Update::ChatTitle(types::UpdateChatTitle {
+            chat_id: 1,
+            title: "b".into(),
+        }));
+        state.apply(&Update::ChatTitle(types::UpdateChatTitle {
+            chat_id: 2,
+            title: "unknown chat".into(),
+        }));
+        state.apply(&position(1, 10));
+
+        let mut received = Vec::new();
+        while let Some(Some(change)) = changes.next().now_or_never() {
+            received.push(change);
+        }
+        assert_eq!(
+            received,
+            vec![
+                Change::Chat(1),
+                Change::Chat(1),
+                Change::ChatList(ChatList::Main)
+            ]
+        );
+        assert_eq!(state.chat(1).unwrap().title, "b");
+    }
+
+    #[test]
+    fn options_are_set_and_removed() {
+        let state = State::new();
+        let option = |value| {
+            Update::Option(types::UpdateOption {
+                name: "version".into(),
+                value,
+            })
+        };
+        state.apply(&option(OptionValue::String(types::OptionValueString {
+            value: "1.8.19".into(),
+        })));
+        assert!(matches!(
+            state.option("version"),
+            Some(OptionValue::String(_))
+        ));
+
+        state.apply(&option(OptionValue::Empty));
+        assert_eq!(state.option("version"), None);
+    }
+}
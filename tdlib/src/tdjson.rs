@@ -6,13 +6,183 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 use std::ffi::{CStr, CString};
-use std::os::raw::{c_char, c_double, c_int};
+#[cfg(not(feature = "dlopen"))]
+use std::os::raw::c_double;
+use std::os::raw::{c_char, c_int};
 
+#[cfg(not(feature = "dlopen"))]
 #[link(name = "tdjson")]
 extern "C" {
     fn td_create_client_id() -> c_int;
     fn td_send(client_id: c_int, request: *const c_char);
     fn td_receive(timeout: c_double) -> *const c_char;
+    fn td_execute(request: *const c_char) -> *const c_char;
+    fn td_set_log_message_callback(max_verbosity_level: c_int, callback: Option<LogCallback>);
+}
+
+/// The callback receiving the log messages of TdLib.
+pub(crate) type LogCallback = extern "C" fn(verbosity_level: c_int, message: *const c_char);
+
+#[cfg(feature = "dlopen")]
+pub(crate) use dynamic::*;
+
+#[cfg(feature = "dlopen")]
+mod dynamic {
+    use super::LogCallback;
+    use crate::LoadError;
+    use once_cell::sync::OnceCell;
+    use serde_json::Value;
+    use std::env;
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_double, c_int};
+    use std::path::{Path, PathBuf};
+
+    /// The environment variable overriding the path of the library.
+    const PATH_VARIABLE: &str = "TDJSON_PATH";
+
+    /// The oldest TdLib version supported by the generated API.
+    const MIN_VERSION: (u32, u32, u32) = (1, 8, 19);
+
+    static LIBRARY: OnceCell<Library> = OnceCell::new();
+
+    struct Library {
+        // Keeps the library loaded for as long as the functions are used
+        _library: libloading::Library,
+        version: String,
+        create_client_id: unsafe extern "C" fn() -> c_int,
+        send: unsafe extern "C" fn(c_int, *const c_char),
+        receive: unsafe extern "C" fn(c_double) -> *const c_char,
+        execute: unsafe extern "C" fn(*const c_char) -> *const c_char,
+        set_log_message_callback: unsafe extern "C" fn(c_int, Option<LogCallback>),
+    }
+
+    impl Library {
+        fn open(path: PathBuf) -> Result<Self, LoadError> {
+            let library = unsafe { libloading::Library::new(&path) }
+                .map_err(|source| LoadError::Open { path, source })?;
+
+            unsafe {
+                let create_client_id = *symbol(&library, "td_create_client_id")?;
+                let send = *symbol(&library, "td_send")?;
+                let receive = *symbol(&library, "td_receive")?;
+                let execute = *symbol(&library, "td_execute")?;
+                let set_log_message_callback = *symbol(&library, "td_set_log_message_callback")?;
+                let version = version(execute)?;
+
+                Ok(Library {
+                    _library: library,
+                    version,
+                    create_client_id,
+                    send,
+                    receive,
+                    execute,
+                    set_log_message_callback,
+                })
+            }
+        }
+    }
+
+    unsafe fn symbol<'a, T>(
+        library: &'a libloading::Library,
+        name: &'static str,
+    ) -> Result<libloading::Symbol<'a, T>, LoadError> {
+        library
+            .get(name.as_bytes())
+            .map_err(|source| LoadError::MissingSymbol { name, source })
+    }
+
+    /// Ask the library for its version, checking that it's supported.
+    unsafe fn version(
+        execute: unsafe extern "C" fn(*const c_char) -> *const c_char,
+    ) -> Result<String, LoadError> {
+        let request = b"{\"@type\":\"getOption\",\"name\":\"version\"}\0";
+        let response = execute(request.as_ptr() as *const c_char)
+            .as_ref()
+            .map(|response| CStr::from_ptr(response).to_string_lossy().into_owned())
+            .ok_or(LoadError::UnknownVersion)?;
+
+        let response: Value =
+            serde_json::from_str(&response).map_err(|_| LoadError::UnknownVersion)?;
+        let version = response["value"]
+            .as_str()
+            .ok_or(LoadError::UnknownVersion)?
+            .to_owned();
+
+        match parse_version(&version) {
+            Some(parsed) if parsed >= MIN_VERSION => Ok(version),
+            Some(_) => Err(LoadError::UnsupportedVersion(version)),
+            None => Err(LoadError::UnknownVersion),
+        }
+    }
+
+    fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+        let mut parts = version.split('.').map(|part| part.parse().ok());
+        Some((parts.next()??, parts.next()??, parts.next()??))
+    }
+
+    fn default_path() -> PathBuf {
+        env::var_os(PATH_VARIABLE)
+            .unwrap_or_else(|| libloading::library_filename("tdjson"))
+            .into()
+    }
+
+    pub fn load(path: Option<&Path>) -> Result<&'static str, LoadError> {
+        let path = path.map(Path::to_path_buf).unwrap_or_else(default_path);
+        LIBRARY
+            .get_or_try_init(|| Library::open(path))
+            .map(|library| library.version.as_str())
+    }
+
+    fn library() -> &'static Library {
+        match load(None) {
+            Ok(_) => LIBRARY.get().unwrap(),
+            Err(e) => panic!(
+                "{}; call `tdlib::load_tdjson` before using TdLib to handle this error",
+                e
+            ),
+        }
+    }
+
+    pub unsafe fn td_create_client_id() -> c_int {
+        (library().create_client_id)()
+    }
+
+    pub unsafe fn td_send(client_id: c_int, request: *const c_char) {
+        (library().send)(client_id, request)
+    }
+
+    pub unsafe fn td_receive(timeout: c_double) -> *const c_char {
+        (library().receive)(timeout)
+    }
+
+    pub unsafe fn td_execute(request: *const c_char) -> *const c_char {
+        (library().execute)(request)
+    }
+
+    pub unsafe fn td_set_log_message_callback(
+        max_verbosity_level: c_int,
+        callback: Option<LogCallback>,
+    ) {
+        (library().set_log_message_callback)(max_verbosity_level, callback)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parse_versions() {
+            assert_eq!(parse_version("1.8.19"), Some((1, 8, 19)));
+            assert_eq!(parse_version("1.8"), None);
+            assert_eq!(parse_version("1.x.0"), None);
+        }
+
+        #[test]
+        fn missing_library() {
+            let error = Library::open("/nonexistent/libtdjson.so".into()).err();
+            assert!(matches!(error, Some(LoadError::Open { .. })));
+        }
+    }
 }
 
 pub(crate) fn create_client() -> i32 {
@@ -31,3 +201,16 @@ pub(crate) fn receive(timeout: f64) -> Option<String> {
             .map(|response| CStr::from_ptr(response).to_string_lossy().into_owned())
     }
 }
+
+pub(crate) fn execute(request: String) -> Option<String> {
+    let cstring = CString::new(request).unwrap();
+    unsafe {
+        td_execute(cstring.as_ptr())
+            .as_ref()
+            .map(|response| CStr::from_ptr(response).to_string_lossy().into_owned())
+    }
+}
+
+pub(crate) fn set_log_message_callback(max_verbosity_level: i32, callback: Option<LogCallback>) {
+    unsafe { td_set_log_message_callback(max_verbosity_level, callback) }
+}
@@ -0,0 +1,50 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fixtures shared by the tests of the modules.
+use crate::types::Message;
+use serde_json::json;
+
+/// An empty text message received in the chat 7 from the user 1, for the
+/// tests to override the fields they need.
+pub(crate) fn message(id: i64) -> Message {
+    serde_json::from_value(json!({
+        "id": id,
+        "sender_id": {"@type": "messageSenderUser", "user_id": 1},
+        "chat_id": 7,
+        "is_outgoing": false,
+        "is_pinned": false,
+        "can_be_edited": false,
+        "can_be_forwarded": false,
+        "can_be_saved": false,
+        "can_be_deleted_only_for_self": false,
+        "can_be_deleted_for_all_users": false,
+        "can_get_added_reactions": false,
+        "can_get_statistics": false,
+        "can_get_message_thread": false,
+        "can_get_viewers": false,
+        "can_get_media_timestamp_links": false,
+        "can_report_reactions": false,
+        "has_timestamped_media": false,
+        "is_channel_post": false,
+        "is_topic_message": false,
+        "contains_unread_mention": false,
+        "date": 0,
+        "edit_date": 0,
+        "unread_reactions": [],
+        "message_thread_id": 0,
+        "self_destruct_in": 0.0,
+        "auto_delete_in": 0.0,
+        "via_bot_user_id": 0,
+        "author_signature": "",
+        "media_album_id": "0",
+        "restriction_reason": "",
+        "content": {"@type": "messageText", "text": {"text": "", "entities": []}}
+    }))
+    .unwrap()
+}
@@ -0,0 +1,198 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A minimal timer running in its own thread, so that timeouts and delays
+//! don't depend on a specific async runtime.
+
+use crate::Error;
+use futures_util::future::{self, Either};
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static TIMER: Lazy<Arc<Timer>> = Lazy::new(Timer::start);
+
+/// The deadline of a delay, with an identifier telling apart the delays with
+/// the same deadline.
+type Key = (Instant, u64);
+
+#[derive(Default)]
+struct Entries {
+    next_id: u64,
+    wakers: BTreeMap<Key, Waker>,
+}
+
+#[derive(Default)]
+struct Timer {
+    entries: Mutex<Entries>,
+    condvar: Condvar,
+}
+
+impl Timer {
+    fn start() -> Arc<Self> {
+        let timer = Arc::new(Timer::default());
+        let timer_clone = timer.clone();
+        thread::Builder::new()
+            .name("tdlib-timer".into())
+            .spawn(move || timer_clone.run())
+            .expect("failed to spawn the timer thread");
+        timer
+    }
+
+    fn run(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            while let Some(entry) = entries.wakers.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                entry.remove().wake();
+            }
+
+            entries = match entries.wakers.keys().next() {
+                Some(&(deadline, _)) => {
+                    let timeout = deadline - now;
+                    self.condvar.wait_timeout(entries, timeout).unwrap().0
+                }
+                None => self.condvar.wait(entries).unwrap(),
+            };
+        }
+    }
+
+    fn register(&self, deadline: Instant, waker: Waker) -> Key {
+        let mut entries = self.entries.lock().unwrap();
+        let key = (deadline, entries.next_id);
+        entries.next_id += 1;
+        entries.wakers.insert(key, waker);
+        drop(entries);
+        self.condvar.notify_one();
+        key
+    }
+
+    /// Replace the waker of a delay, unless its deadline already passed.
+    fn update(&self, key: Key, waker: &Waker) {
+        if let Some(registered) = self.entries.lock().unwrap().wakers.get_mut(&key) {
+            registered.clone_from(waker);
+        }
+    }
+
+    fn unregister(&self, key: Key) {
+        self.entries.lock().unwrap().wakers.remove(&key);
+    }
+}
+
+/// A future completing after a certain duration.
+pub(crate) struct Delay {
+    deadline: Instant,
+    key: Option<Key>,
+}
+
+impl Delay {
+    pub fn new(duration: Duration) -> Self {
+        Delay {
+            deadline: Instant::now() + duration,
+            key: None,
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        match self.key {
+            Some(key) => TIMER.update(key, cx.waker()),
+            None => self.key = Some(TIMER.register(self.deadline, cx.waker().clone())),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Delay {
+    // Remove the entry of a delay dropped before its deadline, like the one
+    // of a request completing before its timeout
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            TIMER.unregister(key);
+        }
+    }
+}
+
+/// Wait for a request to complete, failing with `Error::Timeout` if it takes
+/// longer than `duration`. When the time runs out, the request is dropped and
+/// an eventual late response from TdLib is discarded.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn example(client: tdlib::Client) -> Result<(), tdlib::Error> {
+/// use std::time::Duration;
+///
+/// let me = tdlib::timeout(Duration::from_secs(10), client.get_me()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn timeout<T, F>(duration: Duration, request: F) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    let request = pin!(request);
+    match future::select(request, Delay::new(duration)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(Error::Timeout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn delay_completes() {
+        let start = Instant::now();
+        Delay::new(Duration::from_millis(20)).await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn timeout_elapses() {
+        let result = timeout(
+            Duration::from_millis(10),
+            future::pending::<Result<(), _>>(),
+        );
+        assert!(matches!(result.await, Err(Error::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn timeout_returns_result() {
+        let result = timeout(Duration::from_secs(10), async { Ok(42) });
+        assert_eq!(result.await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn dropped_delay_is_unregistered() {
+        let mut delay = Box::pin(Delay::new(Duration::from_secs(60)));
+        assert!(future::poll_immediate(&mut delay).await.is_none());
+        let key = delay.key.unwrap();
+        assert!(TIMER.entries.lock().unwrap().wakers.contains_key(&key));
+
+        drop(delay);
+        assert!(!TIMER.entries.lock().unwrap().wakers.contains_key(&key));
+    }
+}
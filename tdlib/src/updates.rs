@@ -0,0 +1,202 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::enums::Update;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// What to do when an update arrives and the buffer of an `Updates` stream
+/// is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest buffered update to make room for the new one.
+    #[default]
+    DropOldest,
+    /// Discard the update that just arrived.
+    DropNewest,
+}
+
+struct Queue<T> {
+    updates: VecDeque<T>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// The sending side of an `Updates` stream, or of any other stream of items
+/// pushed by the receiver thread.
+pub(crate) struct Subscription<T = Update> {
+    queue: Mutex<Queue<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl<T> Subscription<T> {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Subscription {
+            queue: Mutex::new(Queue {
+                updates: VecDeque::new(),
+                waker: None,
+                closed: false,
+            }),
+            capacity: capacity.max(1),
+            policy,
+        }
+    }
+
+    /// Buffer an update for the stream. Returns `false` if the stream has
+    /// been dropped or closed, meaning that the subscription can be removed.
+    pub fn push(&self, update: T) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closed {
+            return false;
+        }
+
+        if queue.updates.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    queue.updates.pop_front();
+                }
+                OverflowPolicy::DropNewest => {
+                    log::warn!("Update buffer is full, dropping an update");
+                    return true;
+                }
+            }
+        }
+
+        queue.updates.push_back(update);
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+
+        true
+    }
+
+    /// End the stream once all the buffered updates have been consumed.
+    pub fn close(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.closed = true;
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Take the next item, for the `Stream` implementation of the receiving
+    /// side.
+    pub fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut queue = self.queue.lock().unwrap();
+        match queue.updates.pop_front() {
+            Some(update) => Poll::Ready(Some(update)),
+            None if queue.closed => Poll::Ready(None),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A stream of the updates received for a single `Client`.
+///
+/// Every stream returned by `Client::updates` receives its own copy of each
+/// update, buffered up to the capacity configured with
+/// `ClientBuilder::update_buffer`. The stream ends after the client reaches
+/// `AuthorizationState::Closed` or when the client is dropped.
+pub struct Updates {
+    subscription: Arc<Subscription>,
+}
+
+impl Updates {
+    pub(crate) fn new(subscription: Arc<Subscription>) -> Self {
+        Updates { subscription }
+    }
+}
+
+impl Stream for Updates {
+    type Item = Update;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.subscription.poll_next(cx)
+    }
+}
+
+impl Drop for Updates {
+    fn drop(&mut self) {
+        self.subscription.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enums::OptionValue, types::UpdateOption};
+    use futures_util::{FutureExt, StreamExt};
+
+    fn option_update(name: &str) -> Update {
+        Update::Option(UpdateOption {
+            name: name.into(),
+            value: OptionValue::Empty,
+        })
+    }
+
+    fn collect(updates: &mut Updates) -> Vec<Update> {
+        let mut result = Vec::new();
+        while let Some(Some(update)) = updates.next().now_or_never() {
+            result.push(update);
+        }
+        result
+    }
+
+    #[test]
+    fn drop_oldest_keeps_latest_updates() {
+        let subscription = Arc::new(Subscription::new(2, OverflowPolicy::DropOldest));
+        let mut updates = Updates::new(subscription.clone());
+        for name in ["a", "b", "c"] {
+            assert!(subscription.push(option_update(name)));
+        }
+        assert_eq!(
+            collect(&mut updates),
+            vec![option_update("b"), option_update("c")]
+        );
+    }
+
+    #[test]
+    fn drop_newest_keeps_earliest_updates() {
+        let subscription = Arc::new(Subscription::new(2, OverflowPolicy::DropNewest));
+        let mut updates = Updates::new(subscription.clone());
+        for name in ["a", "b", "c"] {
+            assert!(subscription.push(option_update(name)));
+        }
+        assert_eq!(
+            collect(&mut updates),
+            vec![option_update("a"), option_update("b")]
+        );
+    }
+
+    #[test]
+    fn closed_stream_ends_after_buffered_updates() {
+        let subscription = Arc::new(Subscription::new(2, OverflowPolicy::DropOldest));
+        let mut updates = Updates::new(subscription.clone());
+        subscription.push(option_update("a"));
+        subscription.close();
+        assert!(!subscription.push(option_update("b")));
+        assert_eq!(
+            updates.next().now_or_never(),
+            Some(Some(option_update("a")))
+        );
+        assert_eq!(updates.next().now_or_never(), Some(None));
+    }
+
+    #[test]
+    fn dropped_stream_closes_subscription() {
+        let subscription = Arc::new(Subscription::new(2, OverflowPolicy::DropOldest));
+        drop(Updates::new(subscription.clone()));
+        assert!(!subscription.push(option_update("a")));
+    }
+}
@@ -0,0 +1,756 @@
+// Copyright 2021 - developers of the `tdlib-rs` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Uploads of files sent in messages, from a path, bytes or an async reader.
+//!
+//! The data not available as a local file is handed to TdLib as a generated
+//! file: the file is declared with `inputFileGenerated`, and written with
+//! `writeGeneratedFilePart` when TdLib asks for it with
+//! `updateFileGenerationStart`. The progress of the upload is then reported
+//! by `updateFile`, until `updateMessageSendSucceeded`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use std::sync::Arc;
+//! use tdlib::enums::{FileType, InputMessageContent};
+//! use tdlib::functions::SendMessage;
+//! use tdlib::types::InputMessageDocument;
+//! use tdlib::upload::{UploadSource, Uploader};
+//! use tdlib::Client;
+//!
+//! # async fn example(client: Client, data: Vec<u8>) -> Result<(), tdlib::Error> {
+//! let uploader = Arc::new(Uploader::new());
+//!
+//! // The updates must be applied to the uploader for uploads to complete
+//! let mut updates = client.updates();
+//! let updates_uploader = uploader.clone();
+//! tokio::spawn(async move {
+//!     while let Some(update) = updates.next().await {
+//!         updates_uploader.apply(&update);
+//!     }
+//! });
+//!
+//! let mut upload = uploader.upload(UploadSource::bytes(data), FileType::Document);
+//! let mut progress = upload.progress();
+//! tokio::spawn(async move {
+//!     while let Some(progress) = progress.next().await {
+//!         println!("{:?}", progress);
+//!     }
+//! });
+//!
+//! let message = upload
+//!     .send(&client, |document| SendMessage {
+//!         chat_id: 777,
+//!         message_thread_id: 0,
+//!         reply_to: None,
+//!         options: None,
+//!         reply_markup: None,
+//!         input_message_content: InputMessageContent::InputMessageDocument(
+//!             InputMessageDocument {
+//!                 document,
+//!                 thumbnail: None,
+//!                 disable_content_type_detection: false,
+//!                 caption: None,
+//!             },
+//!         ),
+//!     })
+//!     .await?;
+//! println!("Sent message {}", message.id);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::enums::{self, FileType, InputFile, Update};
+use crate::types::{self, File, InputFileGenerated, InputFileId, InputFileLocal, Message};
+use crate::updates::{OverflowPolicy, Subscription};
+use crate::{Client, Error, Function};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures_core::Stream;
+use futures_util::io::{AsyncRead, AsyncReadExt, Cursor};
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+const DEFAULT_PROGRESS_BUFFER: usize = 64;
+/// The events of an upload are only the starts of the generation of its file
+/// and the result of its sending, so the buffer is never full in practice.
+const EVENT_BUFFER: usize = 16;
+/// The number of results of sending messages kept while an upload waits for
+/// the identifier of its message.
+const UNCLAIMED_BUFFER: usize = 64;
+
+/// The prefix of the `conversion` of the generated files, which identifies
+/// the uploads in `updateFileGenerationStart`. TdLib may reuse a file
+/// generated with the same conversion, even by an earlier process, so it's
+/// followed by the creation time of the uploader and the number of the upload.
+const CONVERSION_PREFIX: &str = "tdlib-rs-upload#";
+
+enum Source {
+    Path(String),
+    Reader {
+        reader: Option<Box<dyn AsyncRead + Send + Unpin>>,
+        expected_size: i64,
+    },
+}
+
+/// The data of an upload.
+pub struct UploadSource(Source);
+
+impl UploadSource {
+    /// Upload a local file, read by TdLib itself.
+    pub fn path(path: impl Into<String>) -> Self {
+        UploadSource(Source::Path(path.into()))
+    }
+
+    /// Upload bytes held in memory.
+    pub fn bytes(bytes: impl Into<Vec<u8>>) -> Self {
+        let bytes = bytes.into();
+        let expected_size = bytes.len() as i64;
+        Self::reader(Cursor::new(bytes), expected_size)
+    }
+
+    /// Upload the data of a reader, read when TdLib asks for it. The expected
+    /// size, in bytes, is only used for the progress, and is 0 if unknown.
+    pub fn reader(reader: impl AsyncRead + Send + Unpin + 'static, expected_size: i64) -> Self {
+        UploadSource(Source::Reader {
+            reader: Some(Box::new(reader)),
+            expected_size,
+        })
+    }
+}
+
+/// The progress of an upload.
+#[derive(Clone, Debug, PartialEq)]
+pub enum UploadProgress {
+    /// The first `size` bytes of the source were handed to TdLib, out of
+    /// `expected_size`, which is 0 if unknown.
+    Generated { size: i64, expected_size: i64 },
+    /// The state of the file, as reported by `updateFile`, with the size
+    /// uploaded so far in `remote.uploaded_size`.
+    File(File),
+}
+
+/// What an upload is told by the updates, besides the progress of its file.
+enum Event {
+    GenerationStart(i64),
+    /// The result of sending the message of the upload.
+    Sent(Result<Box<Message>, types::Error>),
+}
+
+struct Registration {
+    conversion: String,
+    file_id: Option<i32>,
+    /// The identifier of the temporary message of the upload, once known.
+    message_id: Option<i64>,
+    /// Whether the message was sent, but its temporary identifier isn't
+    /// known yet.
+    sending: bool,
+    events: Arc<Subscription<Event>>,
+    progress: Vec<Arc<Subscription<UploadProgress>>>,
+}
+
+impl Registration {
+    fn report(&mut self, progress: UploadProgress) {
+        self.progress
+            .retain(|subscription| subscription.push(progress.clone()));
+    }
+}
+
+#[derive(Default)]
+struct Uploads {
+    uploads: HashMap<u64, Registration>,
+    /// The results of sending messages received while an upload was waiting
+    /// for the identifier of its message, since the response to the request
+    /// sending it may be handled after the result.
+    unclaimed: VecDeque<(i64, Result<Box<Message>, types::Error>)>,
+    sequence: u64,
+}
+
+impl Uploads {
+    /// Hand the result of sending a message to its upload, or keep it if an
+    /// upload doesn't know the identifier of its message yet.
+    fn sent(
+        &mut self,
+        old_message_id: i64,
+        result: impl FnOnce() -> Result<Box<Message>, types::Error>,
+    ) {
+        if let Some(upload) = self
+            .uploads
+            .values()
+            .find(|upload| upload.message_id == Some(old_message_id))
+        {
+            upload.events.push(Event::Sent(result()));
+        } else if self.uploads.values().any(|upload| upload.sending) {
+            if self.unclaimed.len() == UNCLAIMED_BUFFER {
+                self.unclaimed.pop_front();
+            }
+            self.unclaimed.push_back((old_message_id, result()));
+        }
+    }
+
+    /// Record the identifier of the temporary message of an upload, if any,
+    /// once the request sending it was answered, and hand it the result of
+    /// its sending if it was already received.
+    fn finish_sending(&mut self, id: u64, message_id: Option<i64>) {
+        if let Some(upload) = self.uploads.get_mut(&id) {
+            upload.sending = false;
+            upload.message_id = message_id;
+            if let Some(message_id) = message_id {
+                let (claimed, unclaimed) =
+                    self.unclaimed
+                        .drain(..)
+                        .partition::<VecDeque<_>, _>(|(old_message_id, _)| {
+                            *old_message_id == message_id
+                        });
+                self.unclaimed = unclaimed;
+                for (_, result) in claimed {
+                    upload.events.push(Event::Sent(result));
+                }
+            }
+        }
+        if !self.uploads.values().any(|upload| upload.sending) {
+            self.unclaimed.clear();
+        }
+    }
+}
+
+/// Uploads files and sends the messages containing them.
+///
+/// The uploader must be fed every update of the client with `apply`, since
+/// the uploads are driven by updates.
+pub struct Uploader {
+    uploads: Mutex<Uploads>,
+    chunk_size: usize,
+    created: u128,
+}
+
+impl Default for Uploader {
+    fn default() -> Self {
+        Uploader {
+            uploads: Mutex::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        }
+    }
+}
+
+impl Uploader {
+    /// Create an uploader handing the data to TdLib 256 KiB at a time.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the size, in bytes, of the parts of the data handed to TdLib at a
+    /// time.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Prepare the upload of a file of the given type, started by
+    /// `Upload::send`.
+    pub fn upload(&self, source: UploadSource, file_type: FileType) -> Upload<'_> {
+        let events = Arc::new(Subscription::new(EVENT_BUFFER, OverflowPolicy::DropOldest));
+        let mut uploads = self.uploads.lock().unwrap();
+        let id = uploads.sequence;
+        uploads.sequence += 1;
+
+        let conversion = format!("{}{}-{}", CONVERSION_PREFIX, self.created, id);
+        uploads.uploads.insert(
+            id,
+            Registration {
+                conversion: conversion.clone(),
+                file_id: None,
+                message_id: None,
+                sending: false,
+                events: events.clone(),
+                progress: Vec::new(),
+            },
+        );
+
+        Upload {
+            uploader: self,
+            id,
+            conversion,
+            source: source.0,
+            file_type,
+            priority: 1,
+            events,
+        }
+    }
+
+    /// Update the uploads with an update of TdLib, ignoring the updates
+    /// which aren't about them.
+    pub fn apply(&self, update: &Update) {
+        let mut uploads = self.uploads.lock().unwrap();
+        match update {
+            Update::FileGenerationStart(update) => {
+                if let Some(upload) = uploads
+                    .uploads
+                    .values()
+                    .find(|upload| upload.conversion == update.conversion)
+                {
+                    upload
+                        .events
+                        .push(Event::GenerationStart(update.generation_id));
+                }
+            }
+            Update::File(update) => {
+                if let Some(upload) = uploads
+                    .uploads
+                    .values_mut()
+                    .find(|upload| upload.file_id == Some(update.file.id))
+                {
+                    upload.report(UploadProgress::File(update.file.clone()));
+                }
+            }
+            Update::MessageSendSucceeded(update) => uploads.sent(update.old_message_id, || {
+                Ok(Box::new(update.message.clone()))
+            }),
+            Update::MessageSendFailed(update) => {
+                uploads.sent(update.old_message_id, || Err(update.error.clone()))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// An upload prepared by `Uploader::upload`.
+pub struct Upload<'a> {
+    uploader: &'a Uploader,
+    id: u64,
+    conversion: String,
+    source: Source,
+    file_type: FileType,
+    priority: i32,
+    events: Arc<Subscription<Event>>,
+}
+
+impl Upload<'_> {
+    /// Set the priority of the upload, from 1 to 32, a higher value meaning
+    /// a higher priority. The default is 1.
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Get a stream of the progress of the upload, which ends once `send`
+    /// returns. Only the latest 64 states are buffered.
+    pub fn progress(&mut self) -> UploadProgressStream {
+        let subscription = Arc::new(Subscription::new(
+            DEFAULT_PROGRESS_BUFFER,
+            OverflowPolicy::DropOldest,
+        ));
+        self.update_registration(|upload| upload.progress.push(subscription.clone()));
+        UploadProgressStream { subscription }
+    }
+
+    /// Upload the file, and send the message built by `request` around the
+    /// uploaded file, like `sendMessage`. Returns the message once it was
+    /// sent, or the error of TdLib if its sending failed.
+    pub async fn send<F, R>(mut self, client: &Client, request: R) -> Result<Message, Error>
+    where
+        F: Function<Output = enums::Message>,
+        R: FnOnce(InputFile) -> F,
+    {
+        let file = match &self.source {
+            Source::Path(path) => InputFile::Local(InputFileLocal { path: path.clone() }),
+            Source::Reader { expected_size, .. } => InputFile::Generated(InputFileGenerated {
+                original_path: String::new(),
+                conversion: self.conversion.clone(),
+                expected_size: *expected_size,
+            }),
+        };
+
+        // The pattern is refutable with the `unknown-variants` feature
+        #[allow(clippy::infallible_destructuring_match)]
+        let file = match client
+            .preliminary_upload_file(file, Some(self.file_type.clone()), self.priority)
+            .await?
+        {
+            enums::File::File(file) => file,
+            #[cfg(feature = "unknown-variants")]
+            enums::File::Unrecognized(json) => return Err(Error::unrecognized(&json)),
+        };
+        let file_id = file.id;
+        self.update_registration(|upload| {
+            upload.file_id = Some(file_id);
+            upload.report(UploadProgress::File(file));
+            upload.sending = true;
+        });
+
+        // TdLib waits for the end of the upload before sending the message
+        // itself, after answering with a temporary message
+        let request = request(InputFile::Id(InputFileId { id: file_id }));
+        let response = client.send(request).await;
+        let message_id = match &response {
+            Ok(enums::Message::Message(message)) if message.sending_state.is_some() => {
+                Some(message.id)
+            }
+            _ => None,
+        };
+        self.uploader
+            .uploads
+            .lock()
+            .unwrap()
+            .finish_sending(self.id, message_id);
+
+        #[allow(clippy::infallible_destructuring_match)]
+        let message = match response? {
+            enums::Message::Message(message) => message,
+            #[cfg(feature = "unknown-variants")]
+            enums::Message::Unrecognized(json) => return Err(Error::unrecognized(&json)),
+        };
+        if message.sending_state.is_none() {
+            return Ok(message);
+        }
+
+        loop {
+            let event = std::future::poll_fn(|cx| self.events.poll_next(cx)).await;
+            match event {
+                Some(Event::GenerationStart(generation_id)) => {
+                    self.generate(client, generation_id).await
+                }
+                Some(Event::Sent(result)) => {
+                    return result.map(|message| *message).map_err(Error::Tdlib)
+                }
+                None => return Err(Error::Cancelled),
+            }
+        }
+    }
+
+    /// Hand the data to TdLib, finishing the generation with an error if
+    /// reading or writing it failed.
+    async fn generate(&mut self, client: &Client, generation_id: i64) {
+        let error = self
+            .write(client, generation_id)
+            .await
+            .err()
+            .map(|message| types::Error { code: 400, message });
+        if let Err(e) = client.finish_file_generation(generation_id, error).await {
+            log::warn!("Failed to finish the generation of an upload: {}", e);
+        }
+    }
+
+    async fn write(&mut self, client: &Client, generation_id: i64) -> Result<(), String> {
+        let (mut reader, expected_size) = match &mut self.source {
+            Source::Reader {
+                reader,
+                expected_size,
+            } => match reader.take() {
+                Some(reader) => (reader, *expected_size),
+                None => return Err("The source of the upload was already read".into()),
+            },
+            Source::Path(_) => return Err("The upload isn't generated".into()),
+        };
+
+        let mut buffer = vec![0; self.uploader.chunk_size];
+        let mut size = 0;
+        loop {
+            let read = reader.read(&mut buffer).await.map_err(|e| e.to_string())?;
+            if read == 0 {
+                return Ok(());
+            }
+
+            let data = STANDARD.encode(&buffer[..read]);
+            client
+                .write_generated_file_part(generation_id, size, data)
+                .await
+                .map_err(|e| e.to_string())?;
+            size += read as i64;
+            client
+                .set_file_generation_progress(generation_id, expected_size, size)
+                .await
+                .map_err(|e| e.to_string())?;
+            self.report(UploadProgress::Generated {
+                size,
+                expected_size,
+            });
+        }
+    }
+
+    fn report(&self, progress: UploadProgress) {
+        self.update_registration(|upload| upload.report(progress));
+    }
+
+    fn update_registration(&self, update: impl FnOnce(&mut Registration)) {
+        let mut uploads = self.uploader.uploads.lock().unwrap();
+        if let Some(upload) = uploads.uploads.get_mut(&self.id) {
+            update(upload);
+        }
+    }
+}
+
+impl Drop for Upload<'_> {
+    fn drop(&mut self) {
+        let mut uploads = self.uploader.uploads.lock().unwrap();
+        uploads.finish_sending(self.id, None);
+        if let Some(upload) = uploads.uploads.remove(&self.id) {
+            for subscription in &upload.progress {
+                subscription.close();
+            }
+        }
+    }
+}
+
+/// A stream of the progress of an upload, returned by `Upload::progress`.
+pub struct UploadProgressStream {
+    subscription: Arc<Subscription<UploadProgress>>,
+}
+
+impl Stream for UploadProgressStream {
+    type Item = UploadProgress;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.subscription.poll_next(cx)
+    }
+}
+
+impl Drop for UploadProgressStream {
+    fn drop(&mut self) {
+        self.subscription.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::SendMessage;
+    use crate::mock::MockBackend;
+    use crate::test_util;
+    use crate::types::{
+        InputMessageDocument, LocalFile, MessageSendingStatePending, RemoteFile, UpdateFile,
+        UpdateFileGenerationStart, UpdateMessageSendFailed, UpdateMessageSendSucceeded,
+    };
+    use futures_util::StreamExt;
+    use serde_json::{json, Value};
+
+    fn file(id: i32, uploaded_size: i64) -> File {
+        File {
+            id,
+            size: 0,
+            expected_size: 5,
+            local: LocalFile {
+                path: String::new(),
+                can_be_downloaded: false,
+                can_be_deleted: false,
+                is_downloading_active: false,
+                is_downloading_completed: false,
+                download_offset: 0,
+                downloaded_prefix_size: 0,
+                downloaded_size: 0,
+            },
+            remote: RemoteFile {
+                id: String::new(),
+                unique_id: String::new(),
+                is_uploading_active: uploaded_size < 5,
+                is_uploading_completed: uploaded_size == 5,
+                uploaded_size,
+            },
+        }
+    }
+
+    fn message(id: i64, is_pending: bool) -> Message {
+        let mut message = test_util::message(id);
+        message.is_outgoing = true;
+        if is_pending {
+            message.sending_state = Some(enums::MessageSendingState::Pending(
+                MessageSendingStatePending { sending_id: 0 },
+            ));
+        }
+        message
+    }
+
+    fn send_document(document: InputFile) -> SendMessage {
+        SendMessage {
+            chat_id: 7,
+            message_thread_id: 0,
+            reply_to: None,
+            options: None,
+            reply_markup: None,
+            input_message_content: enums::InputMessageContent::InputMessageDocument(
+                InputMessageDocument {
+                    document,
+                    thumbnail: None,
+                    disable_content_type_detection: false,
+                    caption: None,
+                },
+            ),
+        }
+    }
+
+    /// Answer `preliminaryUploadFile` with the file 3, starting its
+    /// generation from another thread if it's generated.
+    fn respond_with_upload(mock: &Arc<MockBackend>, uploader: &Arc<Uploader>) {
+        let uploader = Arc::downgrade(uploader);
+        mock.respond_with("preliminaryUploadFile", move |request: &Value| {
+            if let Some(conversion) = request["file"]["conversion"].as_str() {
+                let uploader = uploader.upgrade().unwrap();
+                let conversion = conversion.to_string();
+                std::thread::spawn(move || {
+                    uploader.apply(&Update::FileGenerationStart(UpdateFileGenerationStart {
+                        generation_id: 11,
+                        original_path: String::new(),
+                        destination_path: "/files/generated".into(),
+                        conversion,
+                    }));
+                });
+            }
+            serde_json::to_value(enums::File::File(file(3, 0))).unwrap()
+        });
+        mock.respond_with("sendMessage", |_| {
+            serde_json::to_value(enums::Message::Message(message(100, true))).unwrap()
+        });
+        mock.respond("writeGeneratedFilePart", json!({"@type": "ok"}));
+        mock.respond("setFileGenerationProgress", json!({"@type": "ok"}));
+    }
+
+    #[tokio::test]
+    async fn generated_upload_until_sent() {
+        let mock = Arc::new(MockBackend::new());
+        let uploader = Arc::new(Uploader::new().chunk_size(3));
+        respond_with_upload(&mock, &uploader);
+        let finishing_uploader = Arc::downgrade(&uploader);
+        mock.respond_with("finishFileGeneration", move |_| {
+            let uploader = finishing_uploader.upgrade().unwrap();
+            std::thread::spawn(move || {
+                // Other messages may be sent meanwhile
+                uploader.apply(&Update::MessageSendSucceeded(UpdateMessageSendSucceeded {
+                    message: message(5, false),
+                    old_message_id: 99,
+                }));
+                uploader.apply(&Update::File(UpdateFile { file: file(3, 5) }));
+                uploader.apply(&Update::MessageSendSucceeded(UpdateMessageSendSucceeded {
+                    message: message(6, false),
+                    old_message_id: 100,
+                }));
+            });
+            json!({"@type": "ok"})
+        });
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let mut upload = uploader.upload(UploadSource::bytes(&b"hello"[..]), FileType::Document);
+        let progress = upload.progress();
+        let message = upload.send(&client, send_document).await.unwrap();
+        assert_eq!(message.id, 6);
+
+        let parts = mock.requests_of_type("writeGeneratedFilePart");
+        let data = parts
+            .iter()
+            .map(|part| STANDARD.decode(part["data"].as_str().unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(data, vec![b"hel".to_vec(), b"lo".to_vec()]);
+        assert_eq!(parts[1]["offset"], 3);
+        assert_eq!(
+            mock.requests_of_type("finishFileGeneration")[0]["generation_id"],
+            "11"
+        );
+        assert_eq!(
+            mock.requests_of_type("sendMessage")[0]["input_message_content"]["document"],
+            json!({"@type": "inputFileId", "id": 3})
+        );
+
+        assert_eq!(
+            progress.collect::<Vec<_>>().await,
+            vec![
+                UploadProgress::File(file(3, 0)),
+                UploadProgress::Generated {
+                    size: 3,
+                    expected_size: 5
+                },
+                UploadProgress::Generated {
+                    size: 5,
+                    expected_size: 5
+                },
+                UploadProgress::File(file(3, 5)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_send_of_local_file() {
+        let mock = Arc::new(MockBackend::new());
+        let uploader = Arc::new(Uploader::new());
+        respond_with_upload(&mock, &uploader);
+        let failing_uploader = Arc::downgrade(&uploader);
+        mock.respond_with("sendMessage", move |_| {
+            let uploader = failing_uploader.upgrade().unwrap();
+            std::thread::spawn(move || {
+                uploader.apply(&Update::MessageSendFailed(UpdateMessageSendFailed {
+                    message: message(100, false),
+                    old_message_id: 100,
+                    error: types::Error {
+                        code: 400,
+                        message: "FILE_PARTS_INVALID".into(),
+                    },
+                }));
+            });
+            serde_json::to_value(enums::Message::Message(message(100, true))).unwrap()
+        });
+
+        let client = Client::builder().backend(mock.clone()).build();
+        let upload = uploader.upload(UploadSource::path("/tmp/video.mp4"), FileType::Video);
+        let result = upload.send(&client, send_document).await;
+        assert!(matches!(result, Err(Error::Tdlib(e)) if e.message == "FILE_PARTS_INVALID"));
+        assert_eq!(
+            mock.requests_of_type("preliminaryUploadFile")[0]["file"],
+            json!({"@type": "inputFileLocal", "path": "/tmp/video.mp4"})
+        );
+        assert!(mock.requests_of_type("writeGeneratedFilePart").is_empty());
+    }
+
+    #[test]
+    fn results_are_only_kept_for_uploads_being_sent() {
+        let uploader = Uploader::new();
+        let _prepared = uploader.upload(UploadSource::path("/tmp/a.txt"), FileType::Document);
+        let sent = |old_message_id| {
+            uploader.apply(&Update::MessageSendSucceeded(UpdateMessageSendSucceeded {
+                message: message(old_message_id + 1, false),
+                old_message_id,
+            }));
+        };
+        sent(10);
+        {
+            let uploads = uploader.uploads.lock().unwrap();
+            assert!(uploads.unclaimed.is_empty());
+            let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+            assert!(uploads.uploads[&0].events.poll_next(&mut cx).is_pending());
+        }
+
+        let _sending = uploader.upload(UploadSource::path("/tmp/b.txt"), FileType::Document);
+        uploader
+            .uploads
+            .lock()
+            .unwrap()
+            .uploads
+            .get_mut(&1)
+            .unwrap()
+            .sending = true;
+        sent(20);
+        sent(30);
+        let mut uploads = uploader.uploads.lock().unwrap();
+        assert_eq!(uploads.unclaimed.len(), 2);
+        uploads.finish_sending(1, Some(30));
+        assert!(uploads.unclaimed.is_empty());
+        let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+        assert!(uploads.uploads[&0].events.poll_next(&mut cx).is_pending());
+        assert!(matches!(
+            uploads.uploads[&1].events.poll_next(&mut cx),
+            Poll::Ready(Some(Event::Sent(Ok(message)))) if message.id == 31
+        ));
+    }
+}